@@ -14,7 +14,6 @@
 #include <sys/wait.h>
 #include <limits.h>
 #include <linux/limits.h>
-#include "/c/cs323/proj4/starter-code/parse.h"
 
 // Write message to stderr using format FORMAT
 #define WARN(format,...) fprintf (stderr, format, __VA_ARGS__)
@@ -29,5 +28,18 @@
 // that is killed has nonzero status; ignores the possibility of stop/continue.
 #define STATUS(x) (WIFEXITED(x) ? WEXITSTATUS(x) : 128+WTERMSIG(x))
 
-// Execute command list CMDLIST and return status of last command executed
-int process (const CMD *cmdList);
+// Set up job control (process group, terminal, signals) if interactive;
+// call after set_arguments(), since a shell running a script is not
+void init_shell (void);
+
+// Set $0 and the positional parameters from the shell's arguments ARGV
+// (from the script ARGV[1] and its arguments, if there is one)
+void set_arguments (int argc, char *argv[]);
+
+// Parse LINE (reading any here document it has from IN) and execute the
+// command on it; return its status, or -1 if there was none (a blank line
+// or a parse error, already reported)
+int run_line (char *line, FILE *in);
+
+// Reap finished background jobs and report them (called before each prompt)
+void reap_jobs (void);
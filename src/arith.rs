@@ -0,0 +1,507 @@
+use crate::builtins::BuiltinIo;
+use crate::expand::{assign, lookup};
+
+// Shell arithmetic, as in $((expr)) and let: 64-bit integers with the C
+// operators and precedence, plus ** for powers.  A variable's value is
+// itself evaluated as an expression, and an unset or empty one is 0.
+
+#[derive(Clone, PartialEq)]
+enum Token {
+    Number(String),
+    Name(String),
+    Op(&'static str),
+}
+
+// Operators, longest first so that <<= is not read as << and =
+static OPERATORS: [&str; 39] = [
+    "<<=", ">>=", "**", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||",
+    "*=", "/=", "%=", "+=", "-=", "&=", "^=", "|=",
+    "+", "-", "*", "/", "%", "<", ">", "&", "^", "|", "!", "~", "?", ":", "=", ",", "(", ")",
+];
+
+// Binary operators from loosest to tightest binding, above ** and unary ops
+static LEVELS: [&[&str]; 8] = [
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<=", ">=", "<", ">"],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+static ASSIGN_OPS: [&str; 11] = ["=", "*=", "/=", "%=", "+=", "-=", "<<=", ">>=", "&=", "^=", "|="];
+
+// how deeply expressions may nest: parentheses, unary operators, the right
+// sides of right-associative ones, and variables whose values are
+// expressions all count
+const MAX_DEPTH: usize = 64;
+
+// Evaluate EXPR; Err holds the message to report
+pub fn evaluate(expr: &str) -> Result<i64, String> {
+    evaluate_at(expr, 0)
+}
+
+fn evaluate_at(expr: &str, depth: usize) -> Result<i64, String> {
+    if depth > MAX_DEPTH {
+        return Err(format!("{}: expression recursion level exceeded", expr));
+    }
+    let tokens = tokenize(expr)?;
+    if tokens.is_empty() {
+        return Ok(0);
+    }
+    let mut parser = Parser { tokens, pos: 0, expr, depth };
+    let value = parser.comma(true)?;
+    if parser.pos < parser.tokens.len() {
+        return Err(parser.syntax_error());
+    }
+    Ok(value)
+}
+
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = expr.trim_start();
+    while !rest.is_empty() {
+        let c = rest.chars().next().unwrap();
+        let word_len = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '#' || c == '@'))
+            .unwrap_or(rest.len());
+        if c.is_ascii_digit() {
+            tokens.push(Token::Number(rest[..word_len].to_string()));
+            rest = &rest[word_len..];
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut name_len = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            // an array element, as in a[i+1], is a name too
+            if rest[name_len..].starts_with('[') {
+                name_len += subscript_len(&rest[name_len..])
+                    .ok_or(format!("{}: syntax error: unterminated subscript", expr))?;
+            }
+            tokens.push(Token::Name(rest[..name_len].to_string()));
+            rest = &rest[name_len..];
+        } else {
+            match OPERATORS.iter().find(|&&op| rest.starts_with(op)) {
+                Some(op) => {
+                    tokens.push(Token::Op(op));
+                    rest = &rest[op.len()..];
+                },
+                None => return Err(format!("{}: syntax error: invalid arithmetic operator (error token is \"{}\")", expr, rest)),
+            }
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+// Length of the [subscript] at the start of TEXT, brackets included
+fn subscript_len(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' if depth == 1 => return Some(i + 1),
+            ']' => depth -= 1,
+            _ => (),
+        }
+    }
+    None
+}
+
+// A number: decimal, 0x hex, 0 octal, or base#digits for bases 2 to 64
+fn parse_number(text: &str) -> Result<i64, String> {
+    let (base, digits) = if let Some((base, digits)) = text.split_once('#') {
+        match base.parse::<u32>() {
+            Ok(base) if (2..=64).contains(&base) => (base, digits),
+            _ => return Err(format!("{}: invalid arithmetic base", text)),
+        }
+    } else if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        (16, hex)
+    } else if text.len() > 1 && text.starts_with('0') {
+        (8, &text[1..])
+    } else {
+        (10, text)
+    };
+    if digits.is_empty() {
+        return Err(format!("{}: invalid number", text));
+    }
+    let mut value: i64 = 0;
+    for c in digits.chars() {
+        let digit = match c {
+            '0'..='9' => c as u32 - '0' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 10,
+            'A'..='Z' if base <= 36 => c as u32 - 'A' as u32 + 10,
+            'A'..='Z' => c as u32 - 'A' as u32 + 36,
+            '@' => 62,
+            '_' => 63,
+            _ => u32::MAX,
+        };
+        if digit >= base {
+            return Err(format!("{}: value too great for base (error token is \"{}\")", text, text));
+        }
+        value = value.wrapping_mul(base as i64).wrapping_add(digit as i64);
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    expr: &'a str,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next_is(&self, op: &str) -> bool {
+        matches!(self.peek(), Some(Token::Op(next)) if *next == op)
+    }
+
+    // Consume operator OP if it is next
+    fn accept(&mut self, op: &str) -> bool {
+        if self.next_is(op) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn syntax_error(&self) -> String {
+        let rest: Vec<String> = self.tokens[self.pos.min(self.tokens.len())..].iter().map(|t| match t {
+            Token::Number(n) | Token::Name(n) => n.clone(),
+            Token::Op(op) => op.to_string(),
+        }).collect();
+        if rest.is_empty() {
+            return format!("{}: syntax error: operand expected", self.expr);
+        }
+        format!("{}: syntax error in expression (error token is \"{}\")", self.expr, rest.join(" "))
+    }
+
+    // The value of variable NAME
+    fn variable(&self, name: &str) -> Result<i64, String> {
+        let value = lookup(name).unwrap_or_default();
+        if value.trim().is_empty() {
+            return Ok(0);
+        }
+        evaluate_at(&value, self.depth + 1)
+    }
+
+    fn set_variable(&self, name: &str, value: i64, eval: bool) -> Result<i64, String> {
+        if eval && assign(name, &value.to_string()).is_err() {
+            return Err(format!("{}: attempted assignment to non-variable", self.expr));
+        }
+        Ok(value)
+    }
+
+    // F(self) one nesting level deeper, an error past MAX_DEPTH rather than
+    // a stack overflow
+    fn nested(&mut self, f: impl FnOnce(&mut Self) -> Result<i64, String>) -> Result<i64, String> {
+        if self.depth >= MAX_DEPTH {
+            return Err(format!("{}: expression recursion level exceeded", self.expr));
+        }
+        self.depth += 1;
+        let value = f(self);
+        self.depth -= 1;
+        value
+    }
+
+    // Each level below evaluates its operands only when EVAL is set, so the
+    // untaken side of && || and ?: has no effects and cannot fail on 1/0
+
+    // expr , expr
+    fn comma(&mut self, eval: bool) -> Result<i64, String> {
+        let mut value = self.assignment(eval)?;
+        while self.accept(",") {
+            value = self.assignment(eval)?;
+        }
+        Ok(value)
+    }
+
+    // name = expr, name += expr, ...
+    fn assignment(&mut self, eval: bool) -> Result<i64, String> {
+        if let (Some(Token::Name(name)), Some(Token::Op(op))) = (self.peek(), self.tokens.get(self.pos + 1)) {
+            if ASSIGN_OPS.contains(op) {
+                let (name, op) = (name.clone(), *op);
+                self.pos += 2;
+                let rhs = self.nested(|p| p.assignment(eval))?;
+                if !eval {
+                    return Ok(0);
+                }
+                let value = match op {
+                    "=" => rhs,
+                    _ => self.apply(&op[..op.len() - 1], self.variable(&name)?, rhs)?,
+                };
+                return self.set_variable(&name, value, eval);
+            }
+        }
+        self.conditional(eval)
+    }
+
+    // cond ? expr : expr
+    fn conditional(&mut self, eval: bool) -> Result<i64, String> {
+        let cond = self.logical_or(eval)?;
+        if !self.accept("?") {
+            return Ok(cond);
+        }
+        let yes = self.comma(eval && cond != 0)?;
+        if !self.accept(":") {
+            return Err(self.syntax_error());
+        }
+        let no = self.nested(|p| p.conditional(eval && cond == 0))?;
+        Ok(if cond != 0 { yes } else { no })
+    }
+
+    fn logical_or(&mut self, eval: bool) -> Result<i64, String> {
+        let mut value = self.logical_and(eval)?;
+        while self.accept("||") {
+            let rhs = self.logical_and(eval && value == 0)?;
+            value = (value != 0 || rhs != 0) as i64;
+        }
+        Ok(value)
+    }
+
+    fn logical_and(&mut self, eval: bool) -> Result<i64, String> {
+        let mut value = self.binary(0, eval)?;
+        while self.accept("&&") {
+            let rhs = self.binary(0, eval && value != 0)?;
+            value = (value != 0 && rhs != 0) as i64;
+        }
+        Ok(value)
+    }
+
+    // The left-associative binary operators of LEVELS[level] and tighter
+    fn binary(&mut self, level: usize, eval: bool) -> Result<i64, String> {
+        if level == LEVELS.len() {
+            return self.power(eval);
+        }
+        let mut value = self.binary(level + 1, eval)?;
+        while let Some(&op) = LEVELS[level].iter().find(|&&op| self.next_is(op)) {
+            self.pos += 1;
+            let rhs = self.binary(level + 1, eval)?;
+            value = if eval { self.apply(op, value, rhs)? } else { 0 };
+        }
+        Ok(value)
+    }
+
+    // base ** exponent, binding to the right
+    fn power(&mut self, eval: bool) -> Result<i64, String> {
+        let base = self.unary(eval)?;
+        if !self.accept("**") {
+            return Ok(base);
+        }
+        let exponent = self.nested(|p| p.power(eval))?;
+        if !eval {
+            return Ok(0);
+        }
+        self.apply("**", base, exponent)
+    }
+
+    fn unary(&mut self, eval: bool) -> Result<i64, String> {
+        for op in ["++", "--"] {
+            if self.accept(op) {
+                let name = match self.peek() {
+                    Some(Token::Name(name)) => name.clone(),
+                    _ => return Err(self.syntax_error()),
+                };
+                self.pos += 1;
+                if !eval {
+                    return Ok(0);
+                }
+                let value = self.variable(&name)?.wrapping_add(if op == "++" { 1 } else { -1 });
+                return self.set_variable(&name, value, eval);
+            }
+        }
+        if self.accept("-") {
+            return Ok(self.nested(|p| p.unary(eval))?.wrapping_neg());
+        }
+        if self.accept("+") {
+            return self.nested(|p| p.unary(eval));
+        }
+        if self.accept("!") {
+            return Ok((self.nested(|p| p.unary(eval))? == 0) as i64);
+        }
+        if self.accept("~") {
+            return Ok(!self.nested(|p| p.unary(eval))?);
+        }
+        self.primary(eval)
+    }
+
+    // number, name, name++, name--, or ( expr )
+    fn primary(&mut self, eval: bool) -> Result<i64, String> {
+        let token = match self.peek() {
+            Some(token) => token.clone(),
+            None => return Err(self.syntax_error()),
+        };
+        self.pos += 1;
+        match token {
+            Token::Number(text) => parse_number(&text),
+            Token::Name(name) => {
+                let value = if eval { self.variable(&name)? } else { 0 };
+                for op in ["++", "--"] {
+                    if self.accept(op) {
+                        let new_value = value.wrapping_add(if op == "++" { 1 } else { -1 });
+                        self.set_variable(&name, new_value, eval)?;
+                    }
+                }
+                Ok(value)
+            },
+            Token::Op("(") => {
+                let value = self.nested(|p| p.comma(eval))?;
+                if !self.accept(")") {
+                    return Err(self.syntax_error());
+                }
+                Ok(value)
+            },
+            Token::Op(_) => {
+                self.pos -= 1;
+                Err(self.syntax_error())
+            },
+        }
+    }
+
+    fn apply(&self, op: &str, a: i64, b: i64) -> Result<i64, String> {
+        let value = match op {
+            "|" => a | b,
+            "^" => a ^ b,
+            "&" => a & b,
+            "==" => (a == b) as i64,
+            "!=" => (a != b) as i64,
+            "<=" => (a <= b) as i64,
+            ">=" => (a >= b) as i64,
+            "<" => (a < b) as i64,
+            ">" => (a > b) as i64,
+            "<<" => a.wrapping_shl(b as u32),
+            ">>" => a.wrapping_shr(b as u32),
+            "+" => a.wrapping_add(b),
+            "-" => a.wrapping_sub(b),
+            "*" => a.wrapping_mul(b),
+            "/" | "%" if b == 0 => return Err(format!("{}: division by 0", self.expr)),
+            "/" => a.wrapping_div(b),
+            "%" => a.wrapping_rem(b),
+            "**" if b < 0 => return Err(format!("{}: exponent less than 0", self.expr)),
+            "**" => a.wrapping_pow(b.min(u32::MAX as i64) as u32),
+            _ => return Err(self.syntax_error()),
+        };
+        Ok(value)
+    }
+}
+
+// let expr ...: evaluate each argument; the status is 0 if the last one is
+// nonzero and 1 if it is zero (or an expression was bad)
+pub fn process_let(argv: &[String], io: &mut BuiltinIo) -> u32 {
+    if argv.len() < 2 {
+        let _ = writeln!(io.stderr, "let: expression expected");
+        return 1;
+    }
+    let mut last = 0;
+    for expr in &argv[1..] {
+        match evaluate(expr) {
+            Ok(value) => last = value,
+            Err(message) => {
+                let _ = writeln!(io.stderr, "let: {}", message);
+                return 1;
+            }
+        }
+    }
+    if last != 0 { 0 } else { 1 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vars::{get_var, set_var};
+
+    fn eval(expr: &str) -> i64 {
+        evaluate(expr).unwrap_or_else(|message| panic!("{}", message))
+    }
+
+    fn error(expr: &str) -> String {
+        evaluate(expr).expect_err(expr)
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("1 + 2 * 3"), 7);
+        assert_eq!(eval("(1 + 2) * 3"), 9);
+        assert_eq!(eval("2 + 3 << 1"), 10);
+        assert_eq!(eval("1 < 2 == 1"), 1);
+        assert_eq!(eval("1 | 2 ^ 3 & 1"), 3);
+        assert_eq!(eval("!0 + ~0"), 0);
+        assert_eq!(eval("-2 ** 2"), 4);
+        assert_eq!(eval("1, 2, 3"), 3);
+    }
+
+    #[test]
+    fn left_associative() {
+        assert_eq!(eval("10 - 4 - 3"), 3);
+        assert_eq!(eval("100 / 10 / 5"), 2);
+        assert_eq!(eval("5 % 3 * 2"), 4);
+    }
+
+    #[test]
+    fn power_is_right_associative() {
+        assert_eq!(eval("2 ** 3 ** 2"), 512);
+        assert_eq!(eval("(2 ** 3) ** 2"), 64);
+        assert!(error("2 ** -1").contains("exponent less than 0"));
+    }
+
+    #[test]
+    fn untaken_branches_are_not_evaluated() {
+        assert_eq!(eval("0 && 1 / 0"), 0);
+        assert_eq!(eval("1 || 1 / 0"), 1);
+        assert_eq!(eval("1 ? 2 : 1 / 0"), 2);
+        assert_eq!(eval("0 ? 1 / 0 : 3"), 3);
+        assert_eq!(eval("0 ? 1 : 0 ? 1 / 0 : 4"), 4);
+        assert!(error("1 && 1 / 0").contains("division by 0"));
+        set_var("n", "0").unwrap();
+        assert_eq!(eval("0 && (n = 5)"), 0);
+        assert_eq!(eval("1 || n++"), 1);
+        assert_eq!(get_var("n").as_deref(), Some("0"));
+    }
+
+    #[test]
+    fn number_bases() {
+        assert_eq!(eval("2#101"), 5);
+        assert_eq!(eval("16#ff"), 255);
+        assert_eq!(eval("36#Z"), 35);
+        assert_eq!(eval("64#A"), 36);
+        assert_eq!(eval("64#@"), 62);
+        assert_eq!(eval("64#_"), 63);
+        assert_eq!(eval("0x1F"), 31);
+        assert_eq!(eval("017"), 15);
+        assert!(error("2#2").contains("value too great for base"));
+        assert!(error("1#1").contains("invalid arithmetic base"));
+        assert!(error("65#1").contains("invalid arithmetic base"));
+        assert!(error("08").contains("value too great for base"));
+    }
+
+    #[test]
+    fn assignments() {
+        assert_eq!(eval("x = 3, x += 2, x"), 5);
+        assert_eq!(eval("y = z = 4"), 4);
+        assert_eq!(get_var("z").as_deref(), Some("4"));
+        assert_eq!(eval("x++ + ++x"), 12);
+        assert!(error("3 = 4").contains("syntax error"));
+    }
+
+    #[test]
+    fn variables_hold_expressions() {
+        set_var("b", "c + 1").unwrap();
+        set_var("c", "2").unwrap();
+        assert_eq!(eval("b * 2"), 6);
+        assert_eq!(eval("unset_name + 1"), 1);
+    }
+
+    #[test]
+    fn recursion_limit() {
+        set_var("loop", "loop").unwrap();
+        assert!(error("loop").contains("recursion level exceeded"));
+        let shallow = format!("{}1{}", "(".repeat(10), ")".repeat(10));
+        assert_eq!(eval(&shallow), 1);
+        let deep = format!("{}1{}", "(".repeat(10000), ")".repeat(10000));
+        assert!(error(&deep).contains("recursion level exceeded"));
+        assert!(error(&format!("{}1", "- ".repeat(10000))).contains("recursion level exceeded"));
+        assert!(error(&format!("2{}", " ** 1".repeat(10000))).contains("recursion level exceeded"));
+    }
+}
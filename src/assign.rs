@@ -0,0 +1,230 @@
+use std::collections::BTreeMap;
+use crate::*;
+use crate::expand::{
+    assign, clear_substitution_status, expand_assignment, expand_fields, param_ref_len, split_subscript,
+    subscript, substitution_status,
+};
+use crate::parse::words;
+use crate::vars::{is_assoc, is_name, set_value, Subscript, Value};
+
+// Assignments beyond the parser's NAME=value: words like a[1]=x, which it
+// takes for arguments, and the arrays of NAME=(value ...).
+
+// NAME=value or NAME[SUB]=value as the name (subscript included) and value
+pub fn assignment_word(word: &str) -> Option<(&str, &str)> {
+    let (name, rest) = word.split_at(param_ref_len(word));
+    let value = rest.strip_prefix('=')?;
+    let base = split_subscript(name).map_or(name, |(base, _)| base);
+    if !is_name(base) {
+        return None;
+    }
+    Some((name, value))
+}
+
+// _cmdList's NAME=value locals, the values unexpanded
+fn locals(_cmdList: &CMD) -> Vec<(&str, &str)> {
+    (0.._cmdList.nLocal as usize)
+        .filter_map(|n| Some((_cmdList.locVar[n].as_deref()?, _cmdList.locVal[n].as_deref()?)))
+        .collect()
+}
+
+// The words between the parentheses of a NAME=(word ...) value
+pub fn compound(value: &str) -> Option<&str> {
+    value.strip_prefix('(')?.strip_suffix(')')
+}
+
+// Assign the unexpanded value RAW to NAME, as NAME=RAW does
+pub fn assign_raw(name: &str, raw: &str) -> Result<(), ()> {
+    match compound(raw) {
+        Some(list) => assign_array(name, list),
+        None => assign(name, &expand_assignment(raw)?),
+    }
+}
+
+// Assign VALUE, already expanded except for any (word ...) list, to NAME,
+// as declare NAME=VALUE does
+pub fn assign_declared(name: &str, value: &str) -> Result<(), ()> {
+    match compound(value) {
+        Some(list) => assign_array(name, list),
+        None => assign(name, value),
+    }
+}
+
+// Assign each value to its NAME, stopping at the first failure (already
+// reported)
+fn assign_all(assignments: &[(&str, &str)]) -> Result<(), ()> {
+    for (name, raw) in assignments {
+        assign_raw(name, raw)?;
+    }
+    Ok(())
+}
+
+// A simple command whose words are all assignments, as in a[1]=x: set the
+// variables in the shell and return the status, or None if _cmdList is an
+// ordinary command
+pub fn assign_words(_cmdList: &Arc<CMD>) -> Option<u32> {
+    let words: Vec<(&str, &str)> = _cmdList.argv.iter()
+        .map(|word| assignment_word(word.as_deref()?))
+        .collect::<Option<_>>()?;
+    if words.is_empty() {
+        return None;
+    }
+    clear_substitution_status();
+    let mut assignments = locals(_cmdList);
+    assignments.extend(words);
+    match assign_all(&assignments) {
+        Ok(()) => Some(substitution_status().unwrap_or(0)),
+        Err(()) => Some(1),
+    }
+}
+
+// [SUB]=value, an element with its subscript in NAME=(...)
+fn element_word(word: &str) -> Option<(&str, &str)> {
+    let close = word.strip_prefix('[')?.find("]=")? + 1;
+    Some((&word[1..close], &word[close + 2..]))
+}
+
+// The array that the WORDS of NAME=(word ...) make: [SUB]=value sets that
+// element, and any other word is expanded like an argument, its fields
+// going into the elements after the last one set
+fn array_value(name: &str, words: &[String]) -> Result<Value, ()> {
+    let assoc = is_assoc(name);
+    let mut indexed = BTreeMap::new();
+    let mut keyed = BTreeMap::new();
+    let mut next = 0;
+    for raw in words {
+        if let Some((sub, value)) = element_word(raw) {
+            let value = expand_assignment(value)?;
+            match subscript(name, sub)? {
+                Subscript::Index(i) => {
+                    indexed.insert(i, value);
+                    next = i + 1;
+                },
+                Subscript::Key(key) => {
+                    keyed.insert(key, value);
+                },
+            }
+            continue;
+        }
+        if assoc {
+            eprintln!("{}: {}: must use subscript when assigning associative array", name, raw);
+            return Err(());
+        }
+        for field in expand_fields(raw)? {
+            indexed.insert(next, field);
+            next += 1;
+        }
+    }
+    Ok(if assoc { Value::Assoc(keyed) } else { Value::Indexed(indexed) })
+}
+
+// NAME=(LIST): set array NAME to the values in LIST
+fn assign_array(name: &str, list: &str) -> Result<(), ()> {
+    if split_subscript(name).is_some() {
+        eprintln!("{}: cannot assign list to array member", name);
+        return Err(());
+    }
+    let words = match words(list) {
+        Some(words) => words,
+        None => {
+            eprintln!("{}: syntax error in ({})", name, list);
+            return Err(());
+        }
+    };
+    set_value(name, array_value(name, &words)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expand::expand_cmd;
+    use crate::parse::parse_line;
+    use crate::process::r_process;
+    use crate::vars::{get_element, get_elements, get_keys, get_var, set_var};
+
+    fn parse(line: &str) -> Arc<CMD> {
+        parse_line(line, &mut || None).unwrap()
+    }
+
+    #[test]
+    fn assignment_words() {
+        assert_eq!(assignment_word("a=1"), Some(("a", "1")));
+        assert_eq!(assignment_word("_x2=a=b"), Some(("_x2", "a=b")));
+        assert_eq!(assignment_word("a[1+1]=x"), Some(("a[1+1]", "x")));
+        assert_eq!(assignment_word("a="), Some(("a", "")));
+        assert_eq!(assignment_word("2a=x"), None);
+        assert_eq!(assignment_word("=x"), None);
+        assert_eq!(assignment_word("a"), None);
+        assert_eq!(assignment_word("a-b=x"), None);
+    }
+
+    #[test]
+    fn words_that_are_all_assignments() {
+        set_var("v", "two words").unwrap();
+        assert_eq!(assign_words(&parse("a[1]=$v a[2]=*")), Some(0));
+        assert_eq!(get_element("a", &Subscript::Index(1)).as_deref(), Some("two words"));
+        assert_eq!(get_element("a", &Subscript::Index(2)).as_deref(), Some("*"));
+        assert_eq!(assign_words(&parse("a[1]=x echo")), None);
+        assert_eq!(assign_words(&parse("echo a[1]=x")), None);
+    }
+
+    #[test]
+    fn declaration_arguments_are_assignments() {
+        set_var("v", "a  b *").unwrap();
+        set_var("HOME", "/home/me").unwrap();
+        let args = |line: &str| -> Vec<String> {
+            expand_cmd(&parse(line)).unwrap().argv.iter().flatten().cloned().collect()
+        };
+        assert_eq!(args("export x=$v y=~/z"), ["export", "x=a  b *", "y=/home/me/z"]);
+        assert_eq!(args("declare -r x=$v"), ["declare", "-r", "x=a  b *"]);
+        assert_eq!(args("readonly x=\"$v\""), ["readonly", "x=a  b *"]);
+        // elsewhere a NAME=value argument is split like any other
+        assert_eq!(args("echo x=$v")[..3], ["echo", "x=a", "b"]);
+        assert_eq!(get_var("x"), None);
+    }
+
+    #[test]
+    fn empty_array() {
+        assert_eq!(r_process(parse("arr=(a b)")), 0);
+        assert_eq!(r_process(parse("arr=()")), 0);
+        assert_eq!(get_elements("arr"), Some(Vec::new()));
+        assert_eq!(expand_assignment("${#arr[@]}"), Ok(String::from("0")));
+    }
+
+    #[test]
+    fn array_of_words() {
+        set_var("v", "d e").unwrap();
+        assert_eq!(r_process(parse("arr=(a \"b c\" $v [7]=x y) n=1")), 0);
+        assert_eq!(get_elements("arr").unwrap(), ["a", "b c", "d", "e", "x", "y"]);
+        assert_eq!(get_keys("arr").unwrap(), ["0", "1", "2", "3", "7", "8"]);
+        assert_eq!(expand_assignment("${#arr[@]} ${arr[1]}"), Ok(String::from("6 b c")));
+        assert_eq!(get_var("n").as_deref(), Some("1"));
+    }
+
+    #[test]
+    fn array_over_several_lines() {
+        let mut lines = vec![String::from("  b"), String::from("c)")].into_iter();
+        let cmd = parse_line("arr=(a", &mut || lines.next()).unwrap();
+        assert_eq!(r_process(cmd), 0);
+        assert_eq!(get_elements("arr").unwrap(), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn declared_associative_array() {
+        set_var("v", "x  y").unwrap();
+        assert_eq!(r_process(parse("declare -A m=([k]=v [\"two words\"]=$v)")), 0);
+        assert!(is_assoc("m"));
+        assert_eq!(get_element("m", &Subscript::Key(String::from("k"))).as_deref(), Some("v"));
+        assert_eq!(get_element("m", &Subscript::Key(String::from("two words"))).as_deref(), Some("x  y"));
+        assert_eq!(expand_assignment("${#m[@]}"), Ok(String::from("2")));
+        // every element of an associative array needs its key
+        assert_eq!(r_process(parse("m=([k]=w bad)")), 1);
+        assert_eq!(expand_assignment("${m[k]}"), Ok(String::from("v")));
+    }
+
+    #[test]
+    fn list_into_an_element() {
+        assert_eq!(r_process(parse("a[1]=(z)")), 1);
+        assert_eq!(get_var("a"), None);
+    }
+}
@@ -0,0 +1,202 @@
+// Brace expansion: a{b,c}d becomes abd acd, and {1..3} becomes 1 2 3.
+// Done on the raw word before any other expansion, so braces inside quotes,
+// escaped braces, ${...} and command substitutions are left alone.
+
+// The words WORD brace-expands to, in order (just WORD if it has no valid
+// brace expression)
+pub fn expand_braces(word: &str) -> Vec<String> {
+    let chars: Vec<char> = word.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] == '{' {
+            if let Some((close, items)) = brace_items(&chars, i) {
+                let prefix: String = chars[..i].iter().collect();
+                let suffix: String = chars[close + 1..].iter().collect();
+                // the items and the rest of the word may hold more braces
+                return items.iter()
+                    .flat_map(|item| expand_braces(&format!("{}{}{}", prefix, item, suffix)))
+                    .collect();
+            }
+        }
+        i = skip(&chars, i) + 1;
+    }
+    vec![word.to_string()]
+}
+
+// The index of the last character of the escape, quoted text, ${...},
+// $(...), $((...)) or `...` starting at I, or I itself if none does: no
+// brace inside those is a brace expression.  Left unclosed, the expansions
+// run to the end of the word, but a quote is just a character.
+fn skip(chars: &[char], i: usize) -> usize {
+    let next = chars.get(i + 1).copied();
+    match chars[i] {
+        '\\' => i + 1,
+        c @ ('\'' | '"') => skip_quoted(chars, i + 1, c),
+        '`' => match skip_quoted(chars, i + 1, '`') {
+            close if close > i => close,
+            _ => chars.len(),
+        },
+        '$' if next == Some('{') => find_end(chars, i + 2, '{', '}').unwrap_or(chars.len()),
+        '$' if next == Some('(') => find_end(chars, i + 2, '(', ')').unwrap_or(chars.len()),
+        _ => i,
+    }
+}
+
+// Index of the quote ending the quoted text that starts at START
+fn skip_quoted(chars: &[char], start: usize, quote: char) -> usize {
+    let mut i = start;
+    while i < chars.len() && chars[i] != quote {
+        if quote != '\'' && chars[i] == '\\' {
+            i += 1;
+        }
+        i += 1;
+    }
+    // an unclosed quote is just a character
+    if i >= chars.len() { start - 1 } else { i }
+}
+
+// Index of the CLOSE ending the ${ or $( whose text starts at START
+fn find_end(chars: &[char], start: usize, open: char, close: char) -> Option<usize> {
+    let mut depth = 0;
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            c if c == open => depth += 1,
+            c if c == close && depth == 0 => return Some(i),
+            c if c == close => depth -= 1,
+            _ => (),
+        }
+        i = skip(chars, i) + 1;
+    }
+    None
+}
+
+// The } matching the { just before START, with the positions of the commas
+// directly inside it
+fn find_close(chars: &[char], start: usize) -> Option<(usize, Vec<usize>)> {
+    let mut depth = 0;
+    let mut commas = Vec::new();
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some((i, commas)),
+            '}' => depth -= 1,
+            ',' if depth == 0 => commas.push(i),
+            _ => (),
+        }
+        i = skip(chars, i) + 1;
+    }
+    None
+}
+
+// If the { at OPEN starts a valid brace expression, the index of its } and
+// the words it stands for
+fn brace_items(chars: &[char], open: usize) -> Option<(usize, Vec<String>)> {
+    let (close, commas) = find_close(chars, open + 1)?;
+    if commas.is_empty() {
+        let body: String = chars[open + 1..close].iter().collect();
+        return sequence(&body).map(|items| (close, items));
+    }
+    let mut items = Vec::new();
+    let mut start = open + 1;
+    for &comma in commas.iter().chain(std::iter::once(&close)) {
+        items.push(chars[start..comma].iter().collect());
+        start = comma + 1;
+    }
+    Some((close, items))
+}
+
+// The words for a sequence expression X..Y or X..Y..STEP, where X and Y are
+// both integers or both single characters
+fn sequence(body: &str) -> Option<Vec<String>> {
+    let parts: Vec<&str> = body.split("..").collect();
+    let step = match parts.len() {
+        2 => 1,
+        3 => parts[2].parse::<i64>().ok()?.unsigned_abs().max(1),
+        _ => return None,
+    };
+    let (from, to) = (parts[0], parts[1]);
+    if let (Ok(a), Ok(b)) = (from.parse::<i64>(), to.parse::<i64>()) {
+        // a leading zero on either end pads every number to the same width
+        let padded = |s: &str| s.trim_start_matches('-').starts_with('0') && s.len() > 1;
+        let width = if padded(from) || padded(to) { from.len().max(to.len()) } else { 0 };
+        return Some(range(a, b, step)?.iter().map(|n| format!("{:0width$}", n, width = width)).collect());
+    }
+    let mut ends = (from.chars(), to.chars());
+    match (ends.0.next(), ends.0.next(), ends.1.next(), ends.1.next()) {
+        (Some(a), None, Some(b), None) if a.is_ascii_alphabetic() && b.is_ascii_alphabetic() => {
+            Some(range(a as i64, b as i64, step)?
+                .into_iter()
+                .filter_map(|c| char::from_u32(c as u32))
+                .map(String::from)
+                .collect())
+        },
+        _ => None,
+    }
+}
+
+// Most words one sequence expression may make; a longer one is left as it
+// is rather than exhausting memory
+const MAX_SEQUENCE: u64 = 1_000_000;
+
+// A to B inclusive, counting by STEP in whichever direction B lies; None if
+// that is more than MAX_SEQUENCE numbers
+fn range(a: i64, b: i64, step: u64) -> Option<Vec<i64>> {
+    let steps = a.abs_diff(b) / step;
+    if steps >= MAX_SEQUENCE {
+        return None;
+    }
+    (0..=steps)
+        .map(|k| if b < a { a.checked_sub_unsigned(k * step) } else { a.checked_add_unsigned(k * step) })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_and_sequences() {
+        assert_eq!(expand_braces("a{b,c}d"), ["abd", "acd"]);
+        assert_eq!(expand_braces("{a,{b,c}}"), ["a", "b", "c"]);
+        assert_eq!(expand_braces("{3..1}"), ["3", "2", "1"]);
+        assert_eq!(expand_braces("{01..10..4}"), ["01", "05", "09"]);
+        assert_eq!(expand_braces("{a..e..2}"), ["a", "c", "e"]);
+    }
+
+    #[test]
+    fn not_brace_expressions() {
+        assert_eq!(expand_braces("{a}"), ["{a}"]);
+        assert_eq!(expand_braces("'{a,b}'"), ["'{a,b}'"]);
+        assert_eq!(expand_braces("\\{a,b}"), ["\\{a,b}"]);
+        assert_eq!(expand_braces("${a,b}"), ["${a,b}"]);
+    }
+
+    #[test]
+    fn substitutions_are_left_alone() {
+        assert_eq!(expand_braces("$(echo x{a,b})"), ["$(echo x{a,b})"]);
+        assert_eq!(expand_braces("\"$(echo {a,b} | wc -w)\""), ["\"$(echo {a,b} | wc -w)\""]);
+        assert_eq!(expand_braces("`echo {a,b}`"), ["`echo {a,b}`"]);
+        assert_eq!(expand_braces("$(( {1..2} ))"), ["$(( {1..2} ))"]);
+        assert_eq!(expand_braces("{x,$(echo a,b)}"), ["x", "$(echo a,b)"]);
+        assert_eq!(expand_braces("{a,b}$(echo ')')"), ["a$(echo ')')", "b$(echo ')')"]);
+    }
+
+    #[test]
+    fn sequence_ends_do_not_overflow() {
+        let word = "{-9223372036854775808..9223372036854775807}";
+        assert_eq!(expand_braces(word), [word]);
+        assert_eq!(expand_braces("{9223372036854775806..9223372036854775807}"),
+                   ["9223372036854775806", "9223372036854775807"]);
+        assert_eq!(expand_braces("{-9223372036854775807..-9223372036854775808}"),
+                   ["-9223372036854775807", "-9223372036854775808"]);
+        assert_eq!(expand_braces("{1..5..-9223372036854775808}"), ["1"]);
+    }
+
+    #[test]
+    fn huge_sequences_are_left_alone() {
+        assert_eq!(expand_braces("{1..2000000}"), ["{1..2000000}"]);
+        assert_eq!(expand_braces("{1..2000000..2}").len(), 1_000_000);
+    }
+}
@@ -0,0 +1,403 @@
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use nix::errno::Errno;
+use crate::arith::process_let;
+use crate::jobs::{process_bg, process_disown, process_fg, process_jobs};
+use crate::options::{process_set, process_shopt};
+use crate::source::process_source;
+use crate::vars::{get_var, process_declare, process_export, process_readonly, process_shift, process_unset, set_var};
+use libc::STDIN_FILENO;
+use nix::unistd::{chdir, getcwd};
+use std::cell::RefCell;
+
+// The streams a builtin reads and writes.  The command's redirections have
+// already been applied to descriptors 0-2 when run() is called.
+pub struct BuiltinIo<'a> {
+    pub stdin: &'a mut dyn Read,
+    pub stdout: &'a mut dyn Write,
+    pub stderr: &'a mut dyn Write,
+}
+
+// A command executed inside the shell (or inside a pipeline stage's child)
+// rather than by execvp.  run() gets the full argv, argv[0] included, and
+// returns the exit status.
+pub trait Builtin {
+    fn name(&self) -> &'static str;
+    fn usage(&self) -> &'static str;
+    fn run(&self, argv: &[String], io: &mut BuiltinIo) -> u32;
+    // POSIX special builtins (set, export, ...) keep prefix assignments
+    fn is_special(&self) -> bool {
+        false
+    }
+}
+
+thread_local! {
+    static REGISTRY: RefCell<Vec<Rc<dyn Builtin>>> = RefCell::new(default_builtins());
+    static DIR_STACK: RefCell<Vec<PathBuf>> = const { RefCell::new(Vec::new()) };
+}
+
+fn default_builtins() -> Vec<Rc<dyn Builtin>> {
+    vec![
+        Rc::new(Cd),
+        Rc::new(Pushd),
+        Rc::new(Popd),
+        Rc::new(Jobs),
+        Rc::new(Fg),
+        Rc::new(Bg),
+        Rc::new(Disown),
+        Rc::new(Set),
+        Rc::new(Shopt),
+        Rc::new(Let),
+        Rc::new(Export),
+        Rc::new(Readonly),
+        Rc::new(Unset),
+        Rc::new(Declare),
+        Rc::new(Shift),
+        Rc::new(Source),
+        Rc::new(Dot),
+        Rc::new(Help),
+    ]
+}
+
+// Add a builtin, replacing any existing one with the same name
+pub fn register_builtin(builtin: Box<dyn Builtin>) {
+    let builtin: Rc<dyn Builtin> = Rc::from(builtin);
+    REGISTRY.with(|registry| {
+        let mut registry = registry.borrow_mut();
+        registry.retain(|b| b.name() != builtin.name());
+        registry.push(builtin);
+    });
+}
+
+pub fn find_builtin(name: &str) -> Option<Rc<dyn Builtin>> {
+    REGISTRY.with(|registry| registry.borrow().iter().find(|b| b.name() == name).cloned())
+}
+
+// Reads the shell's fd 0 directly: a buffered std::io::Stdin could swallow
+// input meant for the commands that follow the builtin.
+pub struct FdStdin;
+
+impl Read for FdStdin {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = unsafe { libc::read(STDIN_FILENO, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+        if n < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(n as usize)
+    }
+}
+
+struct Cd;
+struct Pushd;
+struct Popd;
+struct Jobs;
+struct Fg;
+struct Bg;
+struct Disown;
+struct Set;
+struct Shopt;
+struct Let;
+struct Export;
+struct Readonly;
+struct Unset;
+struct Declare;
+struct Shift;
+struct Source;
+struct Dot;
+struct Help;
+
+impl Builtin for Cd {
+    fn name(&self) -> &'static str { "cd" }
+    fn usage(&self) -> &'static str { "cd [dir]" }
+    fn run(&self, argv: &[String], _io: &mut BuiltinIo) -> u32 { process_cd(argv) }
+}
+
+impl Builtin for Pushd {
+    fn name(&self) -> &'static str { "pushd" }
+    fn usage(&self) -> &'static str { "pushd <dirName>" }
+    fn run(&self, argv: &[String], io: &mut BuiltinIo) -> u32 { process_pushd(argv, io) }
+}
+
+impl Builtin for Popd {
+    fn name(&self) -> &'static str { "popd" }
+    fn usage(&self) -> &'static str { "popd" }
+    fn run(&self, argv: &[String], io: &mut BuiltinIo) -> u32 { process_popd(argv, io) }
+}
+
+impl Builtin for Jobs {
+    fn name(&self) -> &'static str { "jobs" }
+    fn usage(&self) -> &'static str { "jobs" }
+    fn run(&self, argv: &[String], io: &mut BuiltinIo) -> u32 { process_jobs(argv, io) }
+}
+
+impl Builtin for Fg {
+    fn name(&self) -> &'static str { "fg" }
+    fn usage(&self) -> &'static str { "fg [%job]" }
+    fn run(&self, argv: &[String], io: &mut BuiltinIo) -> u32 { process_fg(argv, io) }
+}
+
+impl Builtin for Bg {
+    fn name(&self) -> &'static str { "bg" }
+    fn usage(&self) -> &'static str { "bg [%job]" }
+    fn run(&self, argv: &[String], io: &mut BuiltinIo) -> u32 { process_bg(argv, io) }
+}
+
+impl Builtin for Disown {
+    fn name(&self) -> &'static str { "disown" }
+    fn usage(&self) -> &'static str { "disown [%job]" }
+    fn run(&self, argv: &[String], io: &mut BuiltinIo) -> u32 { process_disown(argv, io) }
+}
+
+impl Builtin for Set {
+    fn name(&self) -> &'static str { "set" }
+    fn usage(&self) -> &'static str { "set [-o|+o] [option] [--] [arg ...]" }
+    fn run(&self, argv: &[String], io: &mut BuiltinIo) -> u32 { process_set(argv, io) }
+    fn is_special(&self) -> bool { true }
+}
+
+impl Builtin for Shopt {
+    fn name(&self) -> &'static str { "shopt" }
+    fn usage(&self) -> &'static str { "shopt [-s|-u] [optname ...]" }
+    fn run(&self, argv: &[String], io: &mut BuiltinIo) -> u32 { process_shopt(argv, io) }
+}
+
+impl Builtin for Let {
+    fn name(&self) -> &'static str { "let" }
+    fn usage(&self) -> &'static str { "let expr [expr ...]" }
+    fn run(&self, argv: &[String], io: &mut BuiltinIo) -> u32 { process_let(argv, io) }
+}
+
+impl Builtin for Export {
+    fn name(&self) -> &'static str { "export" }
+    fn usage(&self) -> &'static str { "export [-n] [-p] [name[=value] ...]" }
+    fn run(&self, argv: &[String], io: &mut BuiltinIo) -> u32 { process_export(argv, io) }
+    fn is_special(&self) -> bool { true }
+}
+
+impl Builtin for Readonly {
+    fn name(&self) -> &'static str { "readonly" }
+    fn usage(&self) -> &'static str { "readonly [-p] [name[=value] ...]" }
+    fn run(&self, argv: &[String], io: &mut BuiltinIo) -> u32 { process_readonly(argv, io) }
+    fn is_special(&self) -> bool { true }
+}
+
+impl Builtin for Unset {
+    fn name(&self) -> &'static str { "unset" }
+    fn usage(&self) -> &'static str { "unset [-v] name ..." }
+    fn run(&self, argv: &[String], io: &mut BuiltinIo) -> u32 { process_unset(argv, io) }
+    fn is_special(&self) -> bool { true }
+}
+
+impl Builtin for Declare {
+    fn name(&self) -> &'static str { "declare" }
+    fn usage(&self) -> &'static str { "declare [-aArx] [-p] [name[=value] ...]" }
+    fn run(&self, argv: &[String], io: &mut BuiltinIo) -> u32 { process_declare(argv, io) }
+}
+
+impl Builtin for Shift {
+    fn name(&self) -> &'static str { "shift" }
+    fn usage(&self) -> &'static str { "shift [n]" }
+    fn run(&self, argv: &[String], io: &mut BuiltinIo) -> u32 { process_shift(argv, io) }
+    fn is_special(&self) -> bool { true }
+}
+
+impl Builtin for Source {
+    fn name(&self) -> &'static str { "source" }
+    fn usage(&self) -> &'static str { "source filename [arguments]" }
+    fn run(&self, argv: &[String], io: &mut BuiltinIo) -> u32 { process_source(argv, io) }
+}
+
+impl Builtin for Dot {
+    fn name(&self) -> &'static str { "." }
+    fn usage(&self) -> &'static str { ". filename [arguments]" }
+    fn run(&self, argv: &[String], io: &mut BuiltinIo) -> u32 { process_source(argv, io) }
+    fn is_special(&self) -> bool { true }
+}
+
+impl Builtin for Help {
+    fn name(&self) -> &'static str { "help" }
+    fn usage(&self) -> &'static str { "help [name ...]" }
+    fn run(&self, argv: &[String], io: &mut BuiltinIo) -> u32 { process_help(argv, io) }
+}
+
+// help: usage of every builtin, or of the ones named
+fn process_help(argv: &[String], io: &mut BuiltinIo) -> u32 {
+    let mut builtins = REGISTRY.with(|registry| registry.borrow().clone());
+    builtins.sort_by_key(|b| b.name());
+    if argv.len() == 1 {
+        for b in builtins.iter() {
+            let _ = writeln!(io.stdout, "{}", b.usage());
+        }
+        return 0;
+    }
+    let mut status = 0;
+    for name in &argv[1..] {
+        match builtins.iter().find(|b| b.name() == name) {
+            Some(b) => {
+                let _ = writeln!(io.stdout, "{}: {}", b.name(), b.usage());
+            },
+            None => {
+                let _ = writeln!(io.stderr, "help: no help topics match `{}'", name);
+                status = 1;
+            }
+        }
+    }
+    status
+}
+
+fn cd_dir_name(dirName: &PathBuf) -> Result<(), Errno> {
+    let oldDir = getcwd();
+    match chdir(dirName) {
+        Ok(_) => {
+            // remember where we were for ~- and record where we are for ~+
+            if let Ok(oldDir) = oldDir {
+                set_dir_var("OLDPWD", &oldDir);
+            }
+            set_dir_var("PWD", &getcwd().unwrap_or(dirName.clone()));
+        },
+        Err(_) => {
+            let err = std::ffi::CString::new("chdir failed").unwrap();
+            unsafe {
+                libc::perror(err.as_ptr());
+                return Err(Errno::ENOENT);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn set_dir_var(name: &str, path: &Path) {
+    let _ = set_var(name, &path.to_string_lossy());
+}
+
+fn process_cd(argv: &[String]) -> u32 {
+    match argv.len() {
+        // "cd"
+        1 => {
+            let key = "HOME";
+            match get_var(key) {
+                Some(path) => {
+                    if let Err(e) = cd_dir_name(&PathBuf::from(path)) {
+                        return e as u32;
+                    };
+                    0
+                },
+                None => {
+                    let err = std::ffi::CString::new("undefined").unwrap();
+                    unsafe {
+                        libc::perror(err.as_ptr());
+                    }
+                    1
+                }
+            }
+        },
+        // "cd [dir]"
+        2 => {
+            if let Err(e) = cd_dir_name(&PathBuf::from(&argv[1])) {
+                return e as u32;
+            };
+            0
+        },
+        _ => {
+            unsafe {
+                let err =std::ffi::CString::new("too many argument").unwrap();
+                libc::perror(err.as_ptr());
+            }
+            1
+        }
+    }
+}
+
+fn print_dir_stack(io: &mut BuiltinIo) -> Result<(), Errno> {
+    let newDir = match getcwd() {
+        Ok(path) => path,
+        Err(_) => {
+            let err = std::ffi::CString::new("failed to get cwd").unwrap();
+            unsafe {
+                libc::perror(err.as_ptr());
+            }
+            return Err(Errno::ENOENT);
+        }
+    };
+    let mut line = format!("{} ", newDir.display());
+    DIR_STACK.with(|stack| {
+        let dir_stack = stack.borrow();
+        if dir_stack.len() == 1 {
+            line.push_str(&format!("{}", dir_stack[0].display()));
+        } else if dir_stack.len() > 1 {
+            for i in (1..dir_stack.len()).rev() {
+                line.push_str(&format!("{} ", dir_stack[i].display()));
+            }
+            line.push_str(&format!("{}", dir_stack[0].display()));
+        }
+    });
+    if writeln!(io.stdout, "{}", line).is_err() {
+        return Err(Errno::last());
+    }
+    Ok(())
+}
+
+fn process_pushd(argv: &[String], io: &mut BuiltinIo) -> u32 {
+    if argv.len() != 2 {
+        let _ = writeln!(io.stderr, "usage: pushd <dirName>");
+        return 1;
+    } else {
+        if let Some(dirName) = argv.get(1) {
+            // get current directory
+            let currDir = match getcwd() {
+                Ok(path) => path,
+                Err(_) => {
+                    let err = std::ffi::CString::new("failed to get cwd").unwrap();
+                    unsafe {
+                        libc::perror(err.as_ptr());
+                    }
+                    return Errno::ENOENT as u32
+                }
+            };
+            // push curr dir to dir stack
+            DIR_STACK.with(|stack| {
+                // clone to avoid borrowing
+                stack.borrow_mut().push(currDir.clone());
+            });
+            // cd to dirName
+            if let Err(e) = cd_dir_name(&PathBuf::from(dirName)){
+                return e as u32;
+            };
+            if let Err(e) = print_dir_stack(io) {
+                return e as u32;
+            }
+        } else {
+            return Errno::EINVAL as u32;
+        }
+    }
+    0
+}
+
+fn process_popd(argv: &[String], io: &mut BuiltinIo) -> u32 {
+    if argv.len() != 1 {
+        let _ = writeln!(io.stderr, "usage: popd");
+        return 1;
+    } else {
+         // pdir is None if stack is empty
+        let pdir = DIR_STACK.with(|stack| {
+            stack.borrow_mut().pop()
+        });
+        if pdir.is_none() {
+            let _ = writeln!(io.stderr, "stack empty");
+            return 1;
+        }
+        // cd to directory
+        if let Some(dir) = pdir {
+            // println!("popped path: {}", dir.display());
+            if let Err(e) = cd_dir_name(&dir) {
+                return e as u32;
+            };
+        }
+        if let Err(e) = print_dir_stack(io) {
+            return e as u32;
+        }
+    }
+    0
+}
+
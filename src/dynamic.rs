@@ -0,0 +1,84 @@
+use std::cell::Cell;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Variables whose value is computed each time they are expanded.  Unsetting
+// one makes it an ordinary variable.
+pub static DYNAMIC_NAMES: [&str; 6] = [
+    "RANDOM", "SECONDS", "EPOCHSECONDS", "EPOCHREALTIME", "BASHPID", "LINENO",
+];
+
+thread_local! {
+    // state of the $RANDOM generator, and the process that seeded it, so
+    // that subshells do not repeat the shell's numbers
+    static RANDOM_STATE: Cell<u32> = const { Cell::new(0) };
+    static RANDOM_PID: Cell<u32> = const { Cell::new(0) };
+    // the time $SECONDS was last set, and the value it was set to
+    static SECONDS_BASE: Cell<(u64, i64)> = const { Cell::new((0, 0)) };
+    // line of the script being read, for $LINENO
+    static LINENO: Cell<u32> = const { Cell::new(0) };
+}
+
+fn now() -> Duration {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+}
+
+// Start counting $SECONDS from zero
+pub fn record_start_time() {
+    SECONDS_BASE.with(|base| base.set((now().as_secs(), 0)));
+}
+
+pub fn line_number() -> u32 {
+    LINENO.with(|n| n.get())
+}
+
+pub fn set_line_number(lineno: u32) {
+    LINENO.with(|n| n.set(lineno));
+}
+
+fn seed_random(seed: u32) {
+    RANDOM_STATE.with(|state| state.set(seed));
+    RANDOM_PID.with(|pid| pid.set(std::process::id()));
+}
+
+// The next number from 0 to 32767
+fn next_random() -> u32 {
+    if RANDOM_PID.with(|pid| pid.get()) != std::process::id() {
+        seed_random(now().subsec_nanos() ^ std::process::id());
+    }
+    RANDOM_STATE.with(|state| {
+        let next = state.get().wrapping_mul(1103515245).wrapping_add(12345);
+        state.set(next);
+        (next >> 16) & 0x7fff
+    })
+}
+
+// Current value of dynamic variable NAME
+pub fn dynamic_value(name: &str) -> String {
+    match name {
+        "RANDOM" => next_random().to_string(),
+        "SECONDS" => {
+            let (since, value) = SECONDS_BASE.with(|base| base.get());
+            (value + now().as_secs().saturating_sub(since) as i64).to_string()
+        },
+        "EPOCHSECONDS" => now().as_secs().to_string(),
+        "EPOCHREALTIME" => {
+            let time = now();
+            format!("{}.{:06}", time.as_secs(), time.subsec_micros())
+        },
+        "BASHPID" => std::process::id().to_string(),
+        "LINENO" => line_number().to_string(),
+        _ => String::new(),
+    }
+}
+
+// Assigning to RANDOM seeds the generator, to SECONDS restarts the count
+// from VALUE, and to LINENO sets the line number; the others ignore it
+pub fn assign_dynamic(name: &str, value: &str) {
+    let number = value.trim().parse::<i64>().unwrap_or(0);
+    match name {
+        "RANDOM" => seed_random(number as u32),
+        "SECONDS" => SECONDS_BASE.with(|base| base.set((now().as_secs(), number))),
+        "LINENO" => set_line_number(number as u32),
+        _ => (),
+    }
+}
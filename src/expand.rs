@@ -0,0 +1,832 @@
+use crate::*;
+use crate::arith::evaluate;
+use crate::assign::{assignment_word, compound};
+use crate::brace::expand_braces;
+use crate::glob::glob;
+use crate::options::option_enabled;
+use crate::pattern;
+use crate::jobs::{interactive, job_control};
+use crate::vars::{
+    get_element, get_elements, get_keys, get_var, is_assoc, last_background, last_status, positional,
+    reading_script, set_element, set_var, shell_name, shell_pid, Subscript,
+};
+use crate::subst::command_output;
+use crate::word::{ansi_c, Word};
+use std::cell::Cell;
+use std::ffi::{CStr, CString};
+
+thread_local! {
+    // exit status of the last command substitution in the command being
+    // expanded, if it had one
+    static SUBST_STATUS: Cell<Option<u32>> = const { Cell::new(None) };
+}
+
+// Status of the last $(...) run while expanding the latest command; this is
+// the command's status when expansion leaves it with no words
+pub fn substitution_status() -> Option<u32> {
+    SUBST_STATUS.with(|s| s.get())
+}
+
+// Forget the last $(...) status before expanding a new command
+pub fn clear_substitution_status() {
+    SUBST_STATUS.with(|s| s.set(None));
+}
+
+// Copy of _cmdList ready to run: brace expressions in the arguments
+// expanded; ~, $NAME, ${...}, $?, $(...) and $((...)) expanded in the
+// arguments and redirection filenames; unquoted expansion results split on
+// $IFS; filename patterns replaced by the matching paths; and quotes
+// removed.  Done when the command is about to run (not when the line is
+// parsed) so that "a=1; echo $a" sees the earlier assignment.  The
+// NAME=value arguments of declare, export and readonly are expanded as
+// assignments instead.  Local values are left for expand_assignment() as
+// each is assigned, so that in "a=1 b=$a" b sees a.  Errors have been
+// reported when Err is returned.
+pub fn expand_cmd(_cmdList: &Arc<CMD>) -> Result<Arc<CMD>, ()> {
+    clear_substitution_status();
+    let mut argv: Vec<Option<String>> = Vec::new();
+    for raw in _cmdList.argv.iter().flatten() {
+        // the NAME=value arguments of a declaration builtin are assignments;
+        // a NAME=(word ...) list is left for the builtin to expand, once
+        // it has made NAME the kind of array it asks for
+        let declaring = argv.first().is_some_and(|cmd| cmd.as_deref().is_some_and(is_declaration));
+        if let Some((name, value)) = assignment_word(raw).filter(|_| declaring) {
+            let value = if compound(value).is_some() { value.to_string() } else { expand_assignment(value)? };
+            argv.push(Some(format!("{}={}", name, value)));
+            continue;
+        }
+        argv.extend(expand_fields(raw)?.into_iter().map(Some));
+    }
+    // a here document's text is not a filename
+    let fromFile = if _cmdList.fromType == Type::RED_IN_HERE as u32 {
+        match &_cmdList.fromFile {
+            Some(text) => Some(expand_text(text, Context::HereDocument, "here document")?.text()),
+            None => None,
+        }
+    } else {
+        expand_redirect(&_cmdList.fromFile)?
+    };
+    let toFile = expand_redirect(&_cmdList.toFile)?;
+    let errFile = expand_redirect(&_cmdList.errFile)?;
+    Ok(Arc::new(CMD {
+        node: _cmdList.node,
+        argc: argv.len() as u32,
+        argv,
+        nLocal: _cmdList.nLocal,
+        locVar: _cmdList.locVar.clone(),
+        locVal: _cmdList.locVal.clone(),
+        fromType: _cmdList.fromType,
+        fromFile,
+        toType: _cmdList.toType,
+        toFile,
+        errType: _cmdList.errType,
+        errFile,
+        left: _cmdList.left.clone(),
+        right: _cmdList.right.clone(),
+    }))
+}
+
+// Builtins whose NAME=value arguments are expanded like assignments, so
+// that "export PATH=$dir:$PATH" is one argument however $dir splits
+static DECLARATION_BUILTINS: [&str; 3] = ["declare", "export", "readonly"];
+
+fn is_declaration(cmd: &str) -> bool {
+    DECLARATION_BUILTINS.contains(&cmd)
+}
+
+// The arguments the word RAW expands to
+pub fn expand_fields(raw: &str) -> Result<Vec<String>, ()> {
+    // an empty argument is kept as it is
+    if raw.is_empty() {
+        return Ok(vec![String::new()]);
+    }
+    // braces first make several words of one; each can then split into
+    // several arguments, or none, as in "echo $unset", and each of those
+    // is matched as a filename pattern
+    let ifs = lookup("IFS").unwrap_or(String::from(" \t\n"));
+    let mut fields = Vec::new();
+    for braced in expand_braces(raw) {
+        for field in expand_word(&braced)?.split(&ifs) {
+            fields.extend(glob_word(&field)?);
+        }
+    }
+    Ok(fields)
+}
+
+// The value of an assignment NAME=VALUE: expanded, but never split or
+// matched against filenames
+pub fn expand_assignment(value: &str) -> Result<String, ()> {
+    Ok(expand_text(value, Context::Assignment, value)?.text())
+}
+
+// A redirection filename, which has to expand to exactly one word (after
+// splitting and filename matching)
+fn expand_redirect(file: &Option<String>) -> Result<Option<String>, ()> {
+    let raw = match file {
+        Some(raw) => raw,
+        None => return Ok(None),
+    };
+    let ifs = lookup("IFS").unwrap_or(String::from(" \t\n"));
+    let mut fields = expand_word(raw)?.split(&ifs);
+    let mut paths = match fields.pop() {
+        Some(field) if fields.is_empty() => glob_word(&field)?,
+        _ => Vec::new(),
+    };
+    if paths.len() != 1 {
+        eprintln!("{}: ambiguous redirect", raw);
+        return Err(());
+    }
+    Ok(paths.pop())
+}
+
+// The paths WORD matches as a filename pattern, or WORD itself if it is not
+// a pattern or matches nothing (an error under shopt -s failglob)
+fn glob_word(word: &Word) -> Result<Vec<String>, ()> {
+    let pattern = word.pattern();
+    if !pattern::has_magic(&pattern) {
+        return Ok(vec![word.text()]);
+    }
+    let paths = glob(&pattern);
+    if !paths.is_empty() {
+        return Ok(paths);
+    }
+    if option_enabled("failglob") {
+        eprintln!("no match: {}", word.text());
+        return Err(());
+    }
+    Ok(vec![word.text()])
+}
+
+// The directory a tilde prefix (the text after ~) names: ~ is $HOME, ~+
+// $PWD, ~- $OLDPWD and ~user that user's home directory
+fn tilde_dir(prefix: &str) -> Option<String> {
+    match prefix {
+        "" => lookup("HOME").or_else(|| home_dir(None)),
+        "+" => lookup("PWD"),
+        "-" => lookup("OLDPWD"),
+        user => home_dir(Some(user)),
+    }
+}
+
+// Home directory of USER (the current user for None) from the password file
+fn home_dir(user: Option<&str>) -> Option<String> {
+    let pw = match user {
+        Some(user) => {
+            let name = CString::new(user).ok()?;
+            unsafe { libc::getpwnam(name.as_ptr()) }
+        },
+        None => unsafe { libc::getpwuid(libc::getuid()) },
+    };
+    if pw.is_null() {
+        return None;
+    }
+    let dir = unsafe { CStr::from_ptr((*pw).pw_dir) };
+    Some(dir.to_string_lossy().into_owned())
+}
+
+// Parameters named by a single punctuation character, as in $? and ${#}
+static SPECIAL_PARAMS: &str = "?$!#@*-";
+
+fn is_name_start(c: char) -> bool {
+    c == '_' || c.is_ascii_alphabetic()
+}
+
+fn is_name_char(c: char) -> bool {
+    c == '_' || c.is_ascii_alphanumeric()
+}
+
+// Current value of parameter NAME, None if it is unset.  $@ and $* are the
+// positional parameters joined into one string.
+pub fn lookup(name: &str) -> Option<String> {
+    if let Some((base, sub)) = split_subscript(name) {
+        return match sub {
+            "@" | "*" => get_elements(base).map(|values| values.join(&ifs_separator(sub))),
+            _ => get_element(base, &subscript(base, sub).ok()?),
+        };
+    }
+    match name {
+        "?" => Some(last_status().to_string()),
+        "$" => Some(shell_pid().to_string()),
+        "!" => last_background().map(|pid| pid.to_string()),
+        "#" => Some(positional().len().to_string()),
+        "-" => Some(option_flags()),
+        "@" | "*" => {
+            let params = positional();
+            if params.is_empty() {
+                return None;
+            }
+            Some(params.join(&ifs_separator(name)))
+        },
+        _ if name.starts_with(|c: char| c.is_ascii_digit()) => {
+            match name.parse::<usize>().ok()? {
+                0 => Some(shell_name()),
+                n => positional().get(n - 1).cloned(),
+            }
+        },
+        _ => get_var(name),
+    }
+}
+
+// $-: the letters of the shell's single-letter options that are on
+fn option_flags() -> String {
+    let mut flags = String::new();
+    if interactive() {
+        flags.push('i');
+    }
+    if job_control() {
+        flags.push('m');
+    }
+    if !reading_script() {
+        flags.push('s');
+    }
+    flags
+}
+
+// What joins the values in $@ or ${NAME[@]} (a space), or $* or ${NAME[*]}
+// (the first character of IFS) when they make one string
+fn ifs_separator(name: &str) -> String {
+    if name == "@" {
+        return String::from(" ");
+    }
+    let ifs = get_var("IFS").unwrap_or(String::from(" \t\n"));
+    ifs.chars().next().map(String::from).unwrap_or_default()
+}
+
+// NAME[SUB] as NAME and SUB, if NAME is a variable name
+pub fn split_subscript(name: &str) -> Option<(&str, &str)> {
+    let (base, rest) = name.split_once('[')?;
+    let sub = rest.strip_suffix(']')?;
+    if !base.starts_with(is_name_start) || !base.chars().all(is_name_char) {
+        return None;
+    }
+    Some((base, sub))
+}
+
+// Evaluate SUB as a subscript of array NAME: it is expanded, and unless NAME
+// is associative, evaluated as an arithmetic expression
+pub fn subscript(name: &str, sub: &str) -> Result<Subscript, ()> {
+    let text = expand_text(sub, Context::Word, sub)?.text();
+    if text.is_empty() {
+        eprintln!("{}[{}]: bad array subscript", name, sub);
+        return Err(());
+    }
+    if is_assoc(name) {
+        return Ok(Subscript::Key(text));
+    }
+    match evaluate(&text) {
+        Ok(index) => Ok(Subscript::Index(index)),
+        Err(message) => {
+            eprintln!("{}", message);
+            Err(())
+        }
+    }
+}
+
+// Set variable NAME (or array element NAME[SUB]), as for ${NAME=word} and
+// ${NAME:=word}
+pub fn assign(name: &str, value: &str) -> Result<(), ()> {
+    if let Some((base, sub)) = split_subscript(name) {
+        return set_element(base, subscript(base, sub)?, value);
+    }
+    if !name.starts_with(is_name_start) {
+        eprintln!("${}: cannot assign in this way", name);
+        return Err(());
+    }
+    set_var(name, value)
+}
+
+// Length of the parameter name at the start of TEXT (0 if there is none)
+fn param_name_len(text: &str) -> usize {
+    match text.chars().next() {
+        Some(c) if c.is_ascii_digit() => text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len()),
+        Some(c) if is_name_start(c) => text.find(|c| !is_name_char(c)).unwrap_or(text.len()),
+        Some(c) if SPECIAL_PARAMS.contains(c) => 1,
+        _ => 0,
+    }
+}
+
+// Length of the parameter at the start of TEXT, with the [subscript] if
+// it is an array element
+pub fn param_ref_len(text: &str) -> usize {
+    let len = param_name_len(text);
+    if !text.starts_with(is_name_start) || !text[len..].starts_with('[') {
+        return len;
+    }
+    let mut depth = 0;
+    for (i, c) in text[len..].char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' if depth == 1 => return len + i + 1,
+            ']' => depth -= 1,
+            _ => (),
+        }
+    }
+    len
+}
+
+// Run the command TEXT for $(TEXT) or `TEXT` and return its output
+fn substitute(text: &str) -> Result<String, ()> {
+    let (output, status) = command_output(text)?;
+    SUBST_STATUS.with(|s| s.set(Some(status)));
+    Ok(output)
+}
+
+// Index of the quote closing the '...' or "..." opened at START - 1
+fn find_close_quote(chars: &[char], start: usize, quote: char) -> Option<usize> {
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            c if c == quote => return Some(i),
+            '\\' if quote == '"' => i += 1,
+            '$' if quote == '"' && i + 1 < chars.len() && chars[i + 1] == '(' => {
+                i = find_close_paren(chars, i + 2)?;
+            },
+            '$' if quote == '"' && i + 1 < chars.len() && chars[i + 1] == '{' => {
+                i = find_close_brace(chars, i + 2)?;
+            },
+            _ => (),
+        }
+        i += 1;
+    }
+    None
+}
+
+// Index of the ) closing the $( whose text starts at START
+fn find_close_paren(chars: &[char], start: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            c @ ('\'' | '"') => i = find_close_quote(chars, i + 1, c).unwrap_or(i),
+            '(' => depth += 1,
+            ')' if depth == 0 => return Some(i),
+            ')' => depth -= 1,
+            _ => (),
+        }
+        i += 1;
+    }
+    None
+}
+
+// Index of the } closing the ${ whose body starts at START, skipping over
+// quotes, nested ${...} and backslash-escaped characters
+fn find_close_brace(chars: &[char], start: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            c @ ('\'' | '"') => i = find_close_quote(chars, i + 1, c).unwrap_or(i),
+            '$' if i + 1 < chars.len() && chars[i + 1] == '{' => {
+                depth += 1;
+                i += 1;
+            },
+            '}' if depth == 0 => return Some(i),
+            '}' => depth -= 1,
+            _ => (),
+        }
+        i += 1;
+    }
+    None
+}
+
+// Where the text being expanded sits: a whole word, an assignment value
+// (where ~ is also expanded after each :), the inside of "...", or the
+// text of a here document (which is like "..." except that " is nothing
+// special)
+#[derive(Clone, Copy, PartialEq)]
+enum Context {
+    Word,
+    Assignment,
+    DoubleQuoted,
+    HereDocument,
+}
+
+// Expand WORD as a command argument or redirection filename
+pub fn expand_word(word: &str) -> Result<Word, ()> {
+    expand_text(word, Context::Word, word)
+}
+
+// Expand ~, $NAME, ${...}, $?, $(...), $((...)) and `...` in TEXT, honoring '...',
+// "...", $'...' and backslash quoting.  A $ that starts none of these, or a
+// quote that is never closed, is kept as a literal character.  WORD is the
+// whole word, for error messages.
+fn expand_text(text: &str, context: Context, word: &str) -> Result<Word, ()> {
+    let chars: Vec<char> = text.chars().collect();
+    let in_quotes = context == Context::DoubleQuoted || context == Context::HereDocument;
+    let escapable = if context == Context::HereDocument { "$`\\\n" } else { "$`\"\\\n" };
+    let mut out = Word::default();
+    let mut i = 0;
+    while i < chars.len() {
+        let tilde_allowed = i == 0 || (context == Context::Assignment && chars[i - 1] == ':');
+        if chars[i] == '~' && tilde_allowed && !in_quotes {
+            let end = chars[i + 1..].iter()
+                .position(|&c| c == '/' || (c == ':' && context == Context::Assignment))
+                .map_or(chars.len(), |n| i + 1 + n);
+            let prefix: String = chars[i + 1..end].iter().collect();
+            if let Some(dir) = tilde_dir(&prefix) {
+                out.push(&dir, true);
+                i = end;
+                continue;
+            }
+        }
+        match chars[i] {
+            c @ ('\'' | '"') if !in_quotes => {
+                let close = match find_close_quote(&chars, i + 1, c) {
+                    Some(close) => close,
+                    None => {
+                        out.push(&c.to_string(), false);
+                        i += 1;
+                        continue;
+                    }
+                };
+                let inner: String = chars[i + 1..close].iter().collect();
+                if c == '\'' {
+                    out.push(&inner, true);
+                } else {
+                    // "$@" with no positional parameters is no word at all,
+                    // not an empty one
+                    if !is_empty_list(&inner) {
+                        out.push("", true);
+                    }
+                    out.append(expand_text(&inner, Context::DoubleQuoted, word)?);
+                }
+                i = close + 1;
+            },
+            '\\' if i + 1 < chars.len() => {
+                // inside "...", \ only quotes the characters special there,
+                // and \ before a newline joins the lines
+                let next = chars[i + 1];
+                if in_quotes && !escapable.contains(next) {
+                    out.push("\\", true);
+                    i += 1;
+                } else if next == '\n' {
+                    i += 2;
+                } else {
+                    out.push(&next.to_string(), true);
+                    i += 2;
+                }
+            },
+            '`' => {
+                // inside backquotes, \\ \` and \$ stand for the character itself
+                let mut command = String::new();
+                let mut end = i + 1;
+                while end < chars.len() && chars[end] != '`' {
+                    if chars[end] == '\\' && end + 1 < chars.len() && "\\`$".contains(chars[end + 1]) {
+                        end += 1;
+                    }
+                    command.push(chars[end]);
+                    end += 1;
+                }
+                if end == chars.len() {
+                    eprintln!("{}: unexpected EOF while looking for matching ``'", word);
+                    return Err(());
+                }
+                out.push_expansion(&substitute(&command)?, in_quotes);
+                i = end + 1;
+            },
+            '$' if i + 1 < chars.len() => {
+                i = expand_dollar(&chars, i, in_quotes, word, &mut out)?;
+            },
+            c => {
+                out.push(&c.to_string(), in_quotes);
+                i += 1;
+            },
+        }
+    }
+    Ok(out)
+}
+
+// Expand the $ expression at chars[i] onto OUT and return the index just
+// past it
+fn expand_dollar(chars: &[char], i: usize, in_quotes: bool, word: &str, out: &mut Word) -> Result<usize, ()> {
+    let next = chars[i + 1];
+    if next == '\'' && !in_quotes {
+        // $'...' with ANSI-C escapes; \' does not end it
+        let mut end = i + 2;
+        while end < chars.len() && chars[end] != '\'' {
+            if chars[end] == '\\' {
+                end += 1;
+            }
+            end += 1;
+        }
+        if end >= chars.len() {
+            out.push("$", false);
+            return Ok(i + 1);
+        }
+        let body: String = chars[i + 2..end].iter().collect();
+        out.push(&ansi_c(&body), true);
+        return Ok(end + 1);
+    }
+    if next == '@' || next == '*' {
+        expand_list(&positional(), next == '*', in_quotes, out);
+        return Ok(i + 2);
+    }
+    // $1 is followed by a literal 0 in $10
+    if SPECIAL_PARAMS.contains(next) || next.is_ascii_digit() {
+        out.push_expansion(&lookup(&next.to_string()).unwrap_or_default(), in_quotes);
+        return Ok(i + 2);
+    }
+    if next == '(' && i + 2 < chars.len() && chars[i + 2] == '(' {
+        // $((expr)), unless the parentheses turn out not to pair up that way,
+        // as in $((cd /tmp); ls)
+        if let Some(close) = find_close_paren(chars, i + 3) {
+            if close + 1 < chars.len() && chars[close + 1] == ')' {
+                let body: String = chars[i + 3..close].iter().collect();
+                let expr = expand_text(&body, Context::DoubleQuoted, word)?.text();
+                match evaluate(&expr) {
+                    Ok(value) => out.push_expansion(&value.to_string(), in_quotes),
+                    Err(message) => {
+                        eprintln!("{}", message);
+                        return Err(());
+                    }
+                }
+                return Ok(close + 2);
+            }
+        }
+    }
+    if next == '(' {
+        let close = match find_close_paren(chars, i + 2) {
+            Some(close) => close,
+            None => {
+                eprintln!("{}: unexpected EOF while looking for matching `)'", word);
+                return Err(());
+            }
+        };
+        let command: String = chars[i + 2..close].iter().collect();
+        out.push_expansion(&substitute(&command)?, in_quotes);
+        return Ok(close + 1);
+    }
+    if next == '{' {
+        let close = match find_close_brace(chars, i + 2) {
+            Some(close) => close,
+            None => {
+                eprintln!("{}: bad substitution", word);
+                return Err(());
+            }
+        };
+        let body: String = chars[i + 2..close].iter().collect();
+        out.append(expand_braced(&body, in_quotes, word)?.into_expansion());
+        return Ok(close + 1);
+    }
+    if is_name_start(next) {
+        let mut end = i + 1;
+        while end < chars.len() && is_name_char(chars[end]) {
+            end += 1;
+        }
+        let name: String = chars[i + 1..end].iter().collect();
+        out.push_expansion(&lookup(&name).unwrap_or_default(), in_quotes);
+        return Ok(end);
+    }
+    out.push("$", in_quotes);
+    Ok(i + 1)
+}
+
+// The values NAME stands for if it is a list: @ and * are the positional
+// parameters, NAME[@] and NAME[*] the elements of an array
+fn list_values(name: &str) -> Option<Vec<String>> {
+    match name {
+        "@" | "*" => Some(positional()),
+        _ => match split_subscript(name)? {
+            (base, "@" | "*") => Some(get_elements(base).unwrap_or_default()),
+            _ => None,
+        },
+    }
+}
+
+// Whether TEXT, the inside of "...", is just $@ or ${NAME[@]} with no
+// values, which makes no word at all rather than an empty one
+fn is_empty_list(text: &str) -> bool {
+    let name = match text.strip_prefix("${").and_then(|t| t.strip_suffix('}')) {
+        Some(name) => name,
+        None if text == "$@" => "@",
+        None => return false,
+    };
+    (name == "@" || name.ends_with("[@]")) && list_values(name).is_some_and(|values| values.is_empty())
+}
+
+// A list such as $@ or ${NAME[@]}: each value makes a separate field, except
+// that with * (STAR) inside "..." they are joined into one by the first
+// character of IFS
+fn expand_list(values: &[String], star: bool, in_quotes: bool, out: &mut Word) {
+    if star && in_quotes {
+        out.push_expansion(&values.join(&ifs_separator("*")), true);
+        return;
+    }
+    for (k, value) in values.iter().enumerate() {
+        if k > 0 {
+            out.push_break();
+        }
+        out.push_expansion(value, in_quotes);
+    }
+}
+
+// Operators allowed after the name in ${NAME<op>word}, longest first so
+// that ## is not read as #
+static OPERATORS: [&str; 12] = [
+    ":-", ":=", ":?", ":+", "##", "%%", "-", "=", "?", "+", "#", "%",
+];
+
+// The value of ${BODY}, quoted if the ${...} was inside "..."; WORD is the
+// whole word, for error messages
+fn expand_braced(body: &str, in_quotes: bool, word: &str) -> Result<Word, ()> {
+    // ${#NAME} is the length of the value, ${#@} or ${#NAME[@]} the number
+    // of values
+    if let Some(name) = body.strip_prefix('#') {
+        if let Some(values) = list_values(name) {
+            return Ok(Word::from_expansion(&values.len().to_string(), in_quotes));
+        }
+        if !name.is_empty() && param_ref_len(name) == name.len() {
+            let length = lookup(name).unwrap_or_default().chars().count();
+            return Ok(Word::from_expansion(&length.to_string(), in_quotes));
+        }
+    }
+    // ${!NAME[@]} is the list of the array's indexes or keys
+    if let Some((base, sub @ ("@" | "*"))) = body.strip_prefix('!').and_then(split_subscript) {
+        let mut out = Word::default();
+        expand_list(&get_keys(base).unwrap_or_default(), sub == "*", in_quotes, &mut out);
+        return Ok(out);
+    }
+    let name_len = param_ref_len(body);
+    let (name, rest) = body.split_at(name_len);
+    if rest.is_empty() {
+        if let Some(values) = list_values(name) {
+            let mut out = Word::default();
+            expand_list(&values, name == "*" || name.ends_with("[*]"), in_quotes, &mut out);
+            return Ok(out);
+        }
+    }
+    let op = OPERATORS.iter().find(|&&op| rest.starts_with(op));
+    let op = match op {
+        Some(&op) if name_len > 0 => op,
+        _ if name_len > 0 && rest.is_empty() => {
+            return Ok(Word::from_expansion(&lookup(name).unwrap_or_default(), in_quotes));
+        },
+        _ => {
+            eprintln!("{}: bad substitution", word);
+            return Err(());
+        }
+    };
+    let context = if in_quotes { Context::DoubleQuoted } else { Context::Word };
+    let operand = &rest[op.len()..];
+    let value = lookup(name);
+    // with the colon, an empty value counts as unset
+    let missing = match &value {
+        Some(v) => op.starts_with(':') && v.is_empty(),
+        None => true,
+    };
+    let value = value.unwrap_or_default();
+    match op.trim_start_matches(':') {
+        "-" if missing => expand_text(operand, context, word),
+        "=" if missing => {
+            let new_value = expand_text(operand, context, word)?.text();
+            assign(name, &new_value)?;
+            Ok(Word::from_expansion(&new_value, in_quotes))
+        },
+        "?" if missing => {
+            let message = expand_text(operand, context, word)?.text();
+            if !message.is_empty() {
+                eprintln!("{}: {}", name, message);
+            } else if op.starts_with(':') {
+                eprintln!("{}: parameter null or not set", name);
+            } else {
+                eprintln!("{}: parameter not set", name);
+            }
+            Err(())
+        },
+        "+" if missing => Ok(Word::default()),
+        "+" => expand_text(operand, context, word),
+        "#" | "##" | "%" | "%%" => {
+            let pat = expand_text(operand, context, word)?.pattern();
+            Ok(Word::from_expansion(&remove_pattern(&value, op, &pat), in_quotes))
+        },
+        _ => Ok(Word::from_expansion(&value, in_quotes)),
+    }
+}
+
+// ${NAME#pat} removes the shortest prefix matching pat, ## the longest;
+// % and %% do the same for suffixes
+fn remove_pattern(value: &str, op: &str, pat: &str) -> String {
+    // every char boundary of value, shortest prefix first
+    let mut cuts: Vec<usize> = value.char_indices().map(|(i, _)| i).collect();
+    cuts.push(value.len());
+    let longest = op.len() == 2;
+    if op.starts_with('#') {
+        if longest {
+            cuts.reverse();
+        }
+        match cuts.iter().find(|&&cut| pattern::matches(pat, &value[..cut])) {
+            Some(&cut) => value[cut..].to_string(),
+            None => value.to_string(),
+        }
+    } else {
+        if !longest {
+            cuts.reverse();
+        }
+        match cuts.iter().find(|&&cut| pattern::matches(pat, &value[cut..])) {
+            Some(&cut) => value[..cut].to_string(),
+            None => value.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vars::{set_last_status, set_positional, unset_var};
+
+    fn expand(word: &str) -> Result<String, ()> {
+        expand_assignment(word)
+    }
+
+    // Each test runs on its own thread, and so with its own variables
+    fn setup() {
+        set_var("set", "value").unwrap();
+        set_var("null", "").unwrap();
+        let _ = unset_var("unset");
+    }
+
+    #[test]
+    fn default_and_alternate_values() {
+        setup();
+        assert_eq!(expand("${set:-d} ${null:-d} ${unset:-d}"), Ok("value d d".to_string()));
+        assert_eq!(expand("[${null-d}] ${unset-d}"), Ok("[] d".to_string()));
+        assert_eq!(expand("${set:+a} [${null:+a}] [${unset:+a}]"), Ok("a [] []".to_string()));
+        assert_eq!(expand("${null+a} [${unset+a}]"), Ok("a []".to_string()));
+        // the operand is only expanded when it is used
+        assert_eq!(expand("${set:-${unset:?boom}}"), Ok("value".to_string()));
+    }
+
+    #[test]
+    fn assign_default() {
+        setup();
+        assert_eq!(expand("${null=x}[${null:=y}] ${unset=z $set}"), Ok("[y] z value".to_string()));
+        assert_eq!(get_var("null").as_deref(), Some("y"));
+        assert_eq!(get_var("unset").as_deref(), Some("z value"));
+        assert!(expand("${1:=x}").is_err());
+    }
+
+    #[test]
+    fn error_if_missing() {
+        setup();
+        assert_eq!(expand("${set:?msg} [${null?msg}]"), Ok("value []".to_string()));
+        assert!(expand("${null:?msg}").is_err());
+        assert!(expand("${unset?}").is_err());
+    }
+
+    #[test]
+    fn lengths() {
+        setup();
+        set_var("x", "héllo").unwrap();
+        assert_eq!(expand("${#x} ${#null} ${#unset}"), Ok("5 0 0".to_string()));
+        set_positional(vec!["a".into(), "bc".into(), "d".into()]);
+        assert_eq!(expand("${#} ${#@} ${#2}"), Ok("3 3 2".to_string()));
+    }
+
+    #[test]
+    fn pattern_removal() {
+        set_var("path", "/usr/local/lib.tar.gz").unwrap();
+        assert_eq!(expand("${path#*/}"), Ok("usr/local/lib.tar.gz".to_string()));
+        assert_eq!(expand("${path##*/}"), Ok("lib.tar.gz".to_string()));
+        assert_eq!(expand("${path%.*}"), Ok("/usr/local/lib.tar".to_string()));
+        assert_eq!(expand("${path%%.*}"), Ok("/usr/local/lib".to_string()));
+        assert_eq!(expand("${path#x}"), Ok("/usr/local/lib.tar.gz".to_string()));
+        // quoted pattern characters match only themselves
+        set_var("star", "a*b*c").unwrap();
+        assert_eq!(expand("${star#*\\*} ${star%\"*\"*}"), Ok("b*c a*b".to_string()));
+        assert_eq!(remove_pattern("aaa", "##", "a"), "aa");
+        assert_eq!(remove_pattern("", "%", "*"), "");
+    }
+
+    #[test]
+    fn bad_substitution() {
+        assert!(expand("${}").is_err());
+        assert!(expand("${a&b}").is_err());
+    }
+
+    #[test]
+    fn special_parameters() {
+        set_positional(vec!["one".into(), "two three".into()]);
+        set_last_status(7);
+        assert_eq!(expand("$# $? $1 ${2}"), Ok("2 7 one two three".to_string()));
+        assert_eq!(expand("$*"), Ok("one two three".to_string()));
+        assert_eq!(expand_fields("\"$@\""), Ok(vec!["one".to_string(), "two three".to_string()]));
+        assert_eq!(expand_fields("$@"), Ok(vec!["one".to_string(), "two".to_string(), "three".to_string()]));
+        assert_eq!(expand("[$3]"), Ok("[]".to_string()));
+    }
+
+    #[test]
+    fn tildes() {
+        set_var("HOME", "/home/me").unwrap();
+        set_var("PWD", "/here").unwrap();
+        set_var("OLDPWD", "/there").unwrap();
+        assert_eq!(expand_fields("~/x"), Ok(vec!["/home/me/x".to_string()]));
+        assert_eq!(expand_fields("~+"), Ok(vec!["/here".to_string()]));
+        assert_eq!(expand_fields("~-/y"), Ok(vec!["/there/y".to_string()]));
+        assert_eq!(expand_fields("'~' a~"), Ok(vec!["~ a~".to_string()]));
+        // in an assignment's value, after each : as well
+        assert_eq!(expand("~:~/b"), Ok("/home/me:/home/me/b".to_string()));
+        assert_eq!(expand_fields("~no_such_user_here"), Ok(vec!["~no_such_user_here".to_string()]));
+    }
+}
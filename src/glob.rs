@@ -0,0 +1,96 @@
+use std::fs;
+use std::path::Path;
+use crate::pattern::{has_magic, matches, unescape};
+
+// The paths matching PATTERN, sorted; empty if nothing matches.  Each
+// /-separated component is matched against one directory level, and a
+// leading . in a name must be matched explicitly.
+pub fn glob(pattern: &str) -> Vec<String> {
+    let parts: Vec<&str> = pattern.split('/').collect();
+    // paths matched so far, spelled the way the pattern spells them
+    let mut paths = vec![String::new()];
+    for (n, &part) in parts.iter().enumerate() {
+        let last = n + 1 == parts.len();
+        let mut next = Vec::new();
+        for path in &paths {
+            let join = |name: &str| if n == 0 { name.to_string() } else { format!("{}/{}", path, name) };
+            if !has_magic(part) {
+                next.push(join(&unescape(part)));
+                continue;
+            }
+            let dir = if n == 0 { "." } else if path.is_empty() { "/" } else { path.as_str() };
+            let entries = match fs::read_dir(dir) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if name.starts_with('.') && !part.starts_with('.') && !part.starts_with("\\.") {
+                    continue;
+                }
+                if matches(part, &name) {
+                    next.push(join(&name));
+                }
+            }
+        }
+        // every component but the last has to name a directory
+        if last {
+            next.retain(|p| fs::symlink_metadata(p).is_ok());
+        } else {
+            next.retain(|p| p.is_empty() || Path::new(p).is_dir());
+        }
+        paths = next;
+    }
+    paths.retain(|p| !p.is_empty());
+    paths.sort();
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A scratch tree: .hidden, visible, sub/.x and sub/y
+    fn tree(name: &str) -> String {
+        let root = format!("{}/glob-{}-{}", std::env::temp_dir().display(), name, std::process::id());
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(format!("{}/sub", root)).unwrap();
+        for file in [".hidden", "visible", "sub/.x", "sub/y"] {
+            fs::write(format!("{}/{}", root, file), "").unwrap();
+        }
+        root
+    }
+
+    fn paths(root: &str, names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| format!("{}/{}", root, name)).collect()
+    }
+
+    #[test]
+    fn leading_dot_must_be_explicit() {
+        let root = tree("dot");
+        assert_eq!(glob(&format!("{}/*", root)), paths(&root, &["sub", "visible"]));
+        assert_eq!(glob(&format!("{}/?hidden", root)), Vec::<String>::new());
+        assert_eq!(glob(&format!("{}/[.]hidden", root)), Vec::<String>::new());
+        assert_eq!(glob(&format!("{}/.*", root)), paths(&root, &[".hidden"]));
+        assert_eq!(glob(&format!("{}/\\.h*", root)), paths(&root, &[".hidden"]));
+        assert_eq!(glob(&format!("{}/*/*", root)), paths(&root, &["sub/y"]));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn doubled_slash_is_kept() {
+        let root = tree("slash");
+        assert_eq!(glob(&format!("{}//sub/*", root)), paths(&root, &["/sub/y"]));
+        assert_eq!(glob(&format!("{}/sub//?", root)), paths(&root, &["sub//y"]));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn only_directories_are_descended() {
+        let root = tree("dirs");
+        assert_eq!(glob(&format!("{}/*/y", root)), paths(&root, &["sub/y"]));
+        assert_eq!(glob(&format!("{}/visible/*", root)), Vec::<String>::new());
+        assert_eq!(glob(&format!("{}/nothing*", root)), Vec::<String>::new());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
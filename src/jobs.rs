@@ -0,0 +1,536 @@
+use nix::errno::Errno;
+use crate::*;
+use crate::builtins::BuiltinIo;
+use crate::process::Entry;
+use crate::vars::reading_script;
+use libc::STDIN_FILENO;
+use nix::sys::signal::{kill, killpg, Signal};
+use nix::sys::wait::{self, WaitPidFlag, WaitStatus};
+use nix::unistd::{getpgrp, getpid, isatty, setpgid, tcgetpgrp, tcsetpgrp, Pid};
+use std::cell::{Cell, RefCell};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum JobState {
+    Running,
+    Stopped,
+    Done,
+}
+
+pub struct Job {
+    id: usize,             // job number, as in %n
+    pgid: Pid,             // process group (first process) of the job
+    procs: Vec<Entry>,     // every process in the job and its last status
+    command: String,       // command text shown by jobs, fg and bg
+    state: JobState,
+    touched: u64,          // when last started/stopped: picks %+ and %-
+    changed: bool,         // finished or stopped in the background, unreported
+}
+
+thread_local! {
+    static JOBS: RefCell<Vec<Job>> = const { RefCell::new(Vec::new()) };
+    static INTERACTIVE: Cell<bool> = const { Cell::new(false) };
+    static JOB_CONTROL: Cell<bool> = const { Cell::new(false) };
+    static SHELL_PGID: Cell<Pid> = const { Cell::new(Pid::from_raw(0)) };
+    static CLOCK: Cell<u64> = const { Cell::new(0) };
+}
+
+// Signals an interactive shell ignores, so that Ctrl-C, Ctrl-\ and Ctrl-Z
+// reach only the foreground job; every forked child gets the defaults back
+const SHELL_SIGNALS: [i32; 5] = [libc::SIGINT, libc::SIGQUIT, libc::SIGTSTP, libc::SIGTTIN, libc::SIGTTOU];
+
+// Turn on job control when stdin is a terminal and the shell is not running
+// a script: wait until the shell is in the foreground, put it in its own
+// process group and take the terminal.
+pub fn init_job_control() {
+    if reading_script() || !isatty(STDIN_FILENO).unwrap_or(false) {
+        return;
+    }
+    loop {
+        match tcgetpgrp(STDIN_FILENO) {
+            Ok(fg) if fg != getpgrp() => {
+                let _ = killpg(getpgrp(), Signal::SIGTTIN);
+            },
+            _ => break,
+        }
+    }
+    for sig in SHELL_SIGNALS {
+        unsafe { libc::signal(sig, libc::SIG_IGN); }
+    }
+    INTERACTIVE.with(|i| i.set(true));
+    let shell = getpid();
+    // fails harmlessly (EPERM) if the shell already leads its session
+    let _ = setpgid(shell, shell);
+    let pgid = getpgrp();
+    if tcsetpgrp(STDIN_FILENO, pgid).is_err() {
+        let err = std::ffi::CString::new("tcsetpgrp").unwrap();
+        unsafe { libc::perror(err.as_ptr()); }
+        return;
+    }
+    SHELL_PGID.with(|p| p.set(pgid));
+    JOB_CONTROL.with(|j| j.set(true));
+}
+
+pub fn interactive() -> bool {
+    INTERACTIVE.with(|i| i.get())
+}
+
+pub fn job_control() -> bool {
+    JOB_CONTROL.with(|j| j.get())
+}
+
+// WUNTRACED only matters when there is a terminal to stop jobs from
+pub fn wait_flags() -> Option<WaitPidFlag> {
+    if job_control() {
+        Some(WaitPidFlag::WUNTRACED)
+    } else {
+        None
+    }
+}
+
+// Called in a child right after fork: join process group PGID (0 starts a
+// new group led by the child), take the terminal if it is a foreground job,
+// restore default signal handling and drop the shell's job-control setup,
+// since subshells do not do it.
+pub fn enter_child(pgid: Pid, foreground: bool) {
+    if job_control() {
+        let pid = getpid();
+        let pgid = if pgid == Pid::from_raw(0) { pid } else { pgid };
+        let _ = setpgid(pid, pgid);
+        if foreground {
+            let _ = tcsetpgrp(STDIN_FILENO, pgid);
+        }
+    }
+    if interactive() {
+        for sig in SHELL_SIGNALS {
+            unsafe { libc::signal(sig, libc::SIG_DFL); }
+        }
+    }
+    INTERACTIVE.with(|i| i.set(false));
+    JOB_CONTROL.with(|j| j.set(false));
+    JOBS.with(|jobs| jobs.borrow_mut().clear());
+}
+
+// Parent side of enter_child(); done in both processes to avoid the race
+pub fn place_in_group(child: Pid, pgid: Pid) {
+    if job_control() {
+        let pgid = if pgid == Pid::from_raw(0) { child } else { pgid };
+        let _ = setpgid(child, pgid);
+    }
+}
+
+pub fn give_terminal(pgid: Pid) {
+    if job_control() {
+        let _ = tcsetpgrp(STDIN_FILENO, pgid);
+    }
+}
+
+pub fn take_terminal() {
+    if job_control() {
+        let _ = tcsetpgrp(STDIN_FILENO, SHELL_PGID.with(|p| p.get()));
+    }
+}
+
+// waitpid() that retries when a signal interrupts it
+pub fn wait_pid(pid: Pid, flags: Option<WaitPidFlag>) -> nix::Result<WaitStatus> {
+    loop {
+        match wait::waitpid(pid, flags) {
+            Err(Errno::EINTR) => continue,
+            result => return result,
+        }
+    }
+}
+
+// Like bash, say why a foreground job died: just a newline after Ctrl-C,
+// the signal's description ("Killed", "Quit (core dumped)") otherwise
+pub fn report_foreground_signal(status: WaitStatus) {
+    if !interactive() {
+        return;
+    }
+    if let WaitStatus::Signaled(_, signal, core) = status {
+        match signal {
+            Signal::SIGINT => eprintln!(),
+            Signal::SIGPIPE => (),
+            _ if core => eprintln!("{} (core dumped)", signal_description(signal)),
+            _ => eprintln!("{}", signal_description(signal)),
+        }
+    }
+}
+
+// Exit status for a finished process, or None if it has not finished
+pub fn status_code(status: WaitStatus) -> Option<u32> {
+    match status {
+        WaitStatus::Exited(_, code) => Some(code as u32),
+        WaitStatus::Signaled(_, signal, _) => Some(128 + signal as u32),
+        _ => None,
+    }
+}
+
+fn tick() -> u64 {
+    CLOCK.with(|c| {
+        c.set(c.get() + 1);
+        c.get()
+    })
+}
+
+// Add a job to the table and return its job number
+pub fn add_job(pgid: Pid, procs: Vec<Entry>, command: String, state: JobState) -> usize {
+    JOBS.with(|jobs| {
+        let mut jobs = jobs.borrow_mut();
+        let id = jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        jobs.push(Job { id, pgid, procs, command, state, touched: tick(), changed: false });
+        id
+    })
+}
+
+// Record a foreground job that was just stopped (Ctrl-Z) and return $?
+pub fn stop_job(pgid: Pid, procs: Vec<Entry>, command: String) -> u32 {
+    let id = add_job(pgid, procs, command, JobState::Stopped);
+    JOBS.with(|jobs| {
+        if let Some(job) = jobs.borrow().iter().find(|job| job.id == id) {
+            eprintln!();
+            eprintln!("{}", job_line(job, '+'));
+        }
+    });
+    128 + Signal::SIGTSTP as u32
+}
+
+// Note the status of PID if it belongs to a job; false if it does not
+pub fn update_job(pid: Pid, status: WaitStatus) -> bool {
+    JOBS.with(|jobs| {
+        let mut jobs = jobs.borrow_mut();
+        for job in jobs.iter_mut() {
+            if let Some(entry) = job.procs.iter_mut().find(|entry| entry.pid == pid) {
+                entry.status = status;
+                refresh_state(job);
+                return true;
+            }
+        }
+        false
+    })
+}
+
+fn refresh_state(job: &mut Job) {
+    let before = job.state;
+    if job.procs.iter().all(|entry| status_code(entry.status).is_some()) {
+        job.state = JobState::Done;
+    } else if job.procs.iter().any(|entry| matches!(entry.status, WaitStatus::Stopped(..))) {
+        if job.state != JobState::Stopped {
+            job.touched = tick();
+        }
+        job.state = JobState::Stopped;
+    } else {
+        job.state = JobState::Running;
+    }
+    if job.state != before && job.state != JobState::Running {
+        job.changed = true;
+    }
+}
+
+// Drop finished jobs from the table
+pub fn remove_done_jobs() {
+    JOBS.with(|jobs| jobs.borrow_mut().retain(|job| job.state != JobState::Done));
+}
+
+// Collect every child that has finished or stopped since the last call, not
+// just one, then report the jobs that changed: "[1]+  Done    sleep 5".
+pub fn reap_jobs() {
+    loop {
+        match wait::waitpid(Pid::from_raw(-1), Some(WaitPidFlag::WNOHANG | WaitPidFlag::WUNTRACED)) {
+            Ok(WaitStatus::StillAlive) => break,
+            Ok(status) => {
+                if let Some(pid) = status.pid() {
+                    update_job(pid, status);
+                }
+            },
+            Err(Errno::EINTR) => continue,
+            // ECHILD: no children left at all
+            Err(_) => break,
+        }
+    }
+    notify_jobs();
+}
+
+// Report the jobs that changed state (only an interactive shell does) and
+// forget those that are done
+fn notify_jobs() {
+    JOBS.with(|jobs| {
+        let mut jobs = jobs.borrow_mut();
+        let mut changed: Vec<usize> = jobs.iter().filter(|job| job.changed).map(|job| job.id).collect();
+        changed.sort();
+        if !interactive() {
+            changed.clear();
+        }
+        for id in changed {
+            if let Some(job) = jobs.iter().find(|job| job.id == id) {
+                eprintln!("{}", job_line(job, job_mark(&jobs, id)));
+            }
+        }
+        for job in jobs.iter_mut() {
+            job.changed = false;
+        }
+        jobs.retain(|job| job.state != JobState::Done);
+    });
+}
+
+// a job's status is that of its last process
+fn last_status(job: &Job) -> u32 {
+    job.procs.last().and_then(|entry| status_code(entry.status)).unwrap_or(0)
+}
+
+// '+' for the current job, '-' for the previous one, ' ' otherwise
+fn job_mark(jobs: &[Job], id: usize) -> char {
+    let mut order: Vec<&Job> = jobs.iter().collect();
+    order.sort_by_key(|job| std::cmp::Reverse(job.touched));
+    match order.iter().position(|job| job.id == id) {
+        Some(0) => '+',
+        Some(1) => '-',
+        _ => ' ',
+    }
+}
+
+fn job_line(job: &Job, mark: char) -> String {
+    let state = match job.state {
+        JobState::Running => "Running".to_string(),
+        JobState::Stopped => "Stopped".to_string(),
+        JobState::Done => match job.procs.last().map(|entry| entry.status) {
+            Some(WaitStatus::Signaled(_, signal, core)) => {
+                let name = signal_description(signal);
+                if core { format!("{} (core dumped)", name) } else { name }
+            },
+            _ => match last_status(job) {
+                0 => "Done".to_string(),
+                status => format!("Exit {}", status),
+            },
+        },
+    };
+    let suffix = if job.state == JobState::Running { " &" } else { "" };
+    format!("[{}]{}  {:<24}{}{}", job.id, mark, state, job.command, suffix)
+}
+
+// "Terminated", "Killed", ... as strsignal() words them
+fn signal_description(signal: Signal) -> String {
+    let desc = unsafe { libc::strsignal(signal as i32) };
+    if desc.is_null() {
+        return signal.as_str().to_string();
+    }
+    unsafe { std::ffi::CStr::from_ptr(desc) }.to_string_lossy().into_owned()
+}
+
+// Resolve a job spec (%n, n, %%, %+, %-, %prefix; none = current job)
+fn find_job(jobs: &[Job], spec: Option<&str>) -> Option<usize> {
+    let mut order: Vec<&Job> = jobs.iter().collect();
+    order.sort_by_key(|job| std::cmp::Reverse(job.touched));
+    let spec = spec.unwrap_or("%+");
+    let body = spec.strip_prefix('%').unwrap_or(spec);
+    match body {
+        "" | "%" | "+" => order.first().map(|job| job.id),
+        "-" => order.get(1).or(order.first()).map(|job| job.id),
+        _ => match body.parse::<usize>() {
+            Ok(n) => jobs.iter().find(|job| job.id == n).map(|job| job.id),
+            Err(_) => order.iter().find(|job| job.command.starts_with(body)).map(|job| job.id),
+        },
+    }
+}
+
+// SIGCONT every process in job ID and mark it running
+fn continue_job(id: usize) -> Result<(), Errno> {
+    JOBS.with(|jobs| {
+        let mut jobs = jobs.borrow_mut();
+        let job = jobs.iter_mut().find(|job| job.id == id).ok_or(Errno::ESRCH)?;
+        if job_control() {
+            killpg(job.pgid, Signal::SIGCONT)?;
+        } else {
+            for entry in job.procs.iter().filter(|entry| status_code(entry.status).is_none()) {
+                kill(entry.pid, Signal::SIGCONT)?;
+            }
+        }
+        for entry in job.procs.iter_mut() {
+            if let WaitStatus::Stopped(..) = entry.status {
+                entry.status = WaitStatus::StillAlive;
+            }
+        }
+        job.state = JobState::Running;
+        job.touched = tick();
+        Ok(())
+    })
+}
+
+// Wait in the foreground for job ID until it finishes or stops again
+fn wait_job(id: usize) -> u32 {
+    let (pgid, pids) = match JOBS.with(|jobs| {
+        jobs.borrow().iter().find(|job| job.id == id).map(|job| {
+            let pending: Vec<Pid> = job.procs.iter()
+                .filter(|entry| status_code(entry.status).is_none())
+                .map(|entry| entry.pid)
+                .collect();
+            (job.pgid, pending)
+        })
+    }) {
+        Some(found) => found,
+        None => return 1,
+    };
+    give_terminal(pgid);
+    for pid in pids {
+        let status = match wait_pid(pid, wait_flags()) {
+            Ok(status) => status,
+            Err(_) => continue,
+        };
+        update_job(pid, status);
+        report_foreground_signal(status);
+        if let WaitStatus::Stopped(..) = status {
+            take_terminal();
+            JOBS.with(|jobs| {
+                let jobs = jobs.borrow();
+                if let Some(job) = jobs.iter().find(|job| job.id == id) {
+                    eprintln!();
+                    eprintln!("{}", job_line(job, job_mark(&jobs, id)));
+                }
+            });
+            JOBS.with(|jobs| {
+                if let Some(job) = jobs.borrow_mut().iter_mut().find(|job| job.id == id) {
+                    job.changed = false;
+                }
+            });
+            return 128 + Signal::SIGTSTP as u32;
+        }
+    }
+    take_terminal();
+    let status = JOBS.with(|jobs| {
+        let mut jobs = jobs.borrow_mut();
+        let status = jobs.iter().find(|job| job.id == id).map(last_status).unwrap_or(0);
+        jobs.retain(|job| job.id != id);
+        status
+    });
+    status
+}
+
+fn job_arg(argv: &[String], name: &str, io: &mut BuiltinIo) -> Result<Option<String>, u32> {
+    match argv.len() {
+        1 => Ok(None),
+        2 => Ok(Some(argv[1].clone())),
+        _ => {
+            let _ = writeln!(io.stderr, "usage: {} [%job]", name);
+            Err(1)
+        }
+    }
+}
+
+// look up the job named on the command line, complaining if there is none
+fn job_from_args(argv: &[String], name: &str, io: &mut BuiltinIo) -> Result<usize, u32> {
+    let spec = job_arg(argv, name, io)?;
+    match JOBS.with(|jobs| find_job(&jobs.borrow(), spec.as_deref())) {
+        Some(id) => Ok(id),
+        None => {
+            let _ = writeln!(io.stderr, "{}: {}: no such job", name, spec.as_deref().unwrap_or("current"));
+            Err(1)
+        }
+    }
+}
+
+pub fn process_jobs(argv: &[String], io: &mut BuiltinIo) -> u32 {
+    if argv.len() != 1 {
+        let _ = writeln!(io.stderr, "usage: jobs");
+        return 1;
+    }
+    JOBS.with(|jobs| {
+        let jobs = jobs.borrow();
+        let mut listed: Vec<&Job> = jobs.iter().collect();
+        listed.sort_by_key(|job| job.id);
+        for job in listed {
+            let _ = writeln!(io.stdout, "{}", job_line(job, job_mark(&jobs, job.id)));
+        }
+    });
+    remove_done_jobs();
+    0
+}
+
+pub fn process_fg(argv: &[String], io: &mut BuiltinIo) -> u32 {
+    let id = match job_from_args(argv, "fg", io) {
+        Ok(id) => id,
+        Err(status) => return status,
+    };
+    JOBS.with(|jobs| {
+        if let Some(job) = jobs.borrow().iter().find(|job| job.id == id) {
+            let _ = writeln!(io.stdout, "{}", job.command);
+        }
+    });
+    let _ = io.stdout.flush();
+    if let Err(e) = continue_job(id) {
+        let _ = writeln!(io.stderr, "fg: {}", e.desc());
+        return 1;
+    }
+    wait_job(id)
+}
+
+pub fn process_bg(argv: &[String], io: &mut BuiltinIo) -> u32 {
+    let id = match job_from_args(argv, "bg", io) {
+        Ok(id) => id,
+        Err(status) => return status,
+    };
+    if let Err(e) = continue_job(id) {
+        let _ = writeln!(io.stderr, "bg: {}", e.desc());
+        return 1;
+    }
+    JOBS.with(|jobs| {
+        let jobs = jobs.borrow();
+        if let Some(job) = jobs.iter().find(|job| job.id == id) {
+            let _ = writeln!(io.stdout, "[{}]{} {} &", job.id, job_mark(&jobs, id), job.command);
+        }
+    });
+    0
+}
+
+pub fn process_disown(argv: &[String], io: &mut BuiltinIo) -> u32 {
+    let id = match job_from_args(argv, "disown", io) {
+        Ok(id) => id,
+        Err(status) => return status,
+    };
+    JOBS.with(|jobs| jobs.borrow_mut().retain(|job| job.id != id));
+    0
+}
+
+// Rebuild shell text for a command tree, for job listings
+pub fn command_text(_cmdList: &CMD) -> String {
+    let left = || _cmdList.left.as_ref().map(|c| command_text(c)).unwrap_or_default();
+    let right = || _cmdList.right.as_ref().map(|c| command_text(c)).unwrap_or_default();
+    let join = |op: &str| {
+        let (l, r) = (left(), right());
+        if r.is_empty() { format!("{}{}", l, op.trim_end()) } else { format!("{}{}{}", l, op, r) }
+    };
+    let mut text = match _cmdList.node {
+        x if x == Type::PIPE as u32 => join(" | "),
+        x if x == Type::SEP_AND as u32 => join(" && "),
+        x if x == Type::SEP_OR as u32 => join(" || "),
+        x if x == Type::SEP_END as u32 => join("; "),
+        x if x == Type::SEP_BG as u32 => join(" & "),
+        x if x == Type::SUBCMD as u32 => format!("({})", left()),
+        _ => {
+            let mut words: Vec<String> = Vec::new();
+            for n in 0.._cmdList.nLocal as usize {
+                if let (Some(var), Some(val)) = (&_cmdList.locVar[n], &_cmdList.locVal[n]) {
+                    words.push(format!("{}={}", var, val));
+                }
+            }
+            words.extend(_cmdList.argv.iter().flatten().cloned());
+            words.join(" ")
+        }
+    };
+    if _cmdList.fromType == Type::RED_IN_HERE as u32 {
+        text.push_str(" << HERE");
+    } else if let Some(file) = &_cmdList.fromFile {
+        text.push_str(&format!(" < {}", file));
+    }
+    if let Some(file) = &_cmdList.toFile {
+        let op = match _cmdList.toType {
+            x if x == Type::RED_OUT_APP as u32 => ">>",
+            x if x == Type::RED_OUT_ERR as u32 => "&>",
+            _ => ">",
+        };
+        text.push_str(&format!(" {} {}", op, file));
+    }
+    if let Some(file) = &_cmdList.errFile {
+        let op = if _cmdList.errType == Type::RED_ERR_APP as u32 { "2>>" } else { "2>" };
+        text.push_str(&format!(" {} {}", op, file));
+    }
+    text
+}
@@ -9,8 +9,25 @@ use std::ffi::CStr;
 use std::str;
 use std::sync::Arc;
 
+mod arith;
+mod assign;
+mod brace;
+mod builtins;
+mod dynamic;
+mod expand;
+mod glob;
+mod jobs;
+mod options;
+mod parse;
+mod pattern;
 mod process;
+mod redirect;
+mod source;
+mod subst;
+mod vars;
+mod word;
 use process::r_process;
+pub use builtins::{register_builtin, Builtin, BuiltinIo};
 
 pub enum Type {
   // Token types used by tokenize() et al.
@@ -22,10 +39,10 @@ pub enum Type {
 
   RED_OUT,          // >    Redirect stdout to file
   RED_OUT_APP,      // >>   Append stdout to file
-  RED_OUT_ERR,      // &>   Redirect stdout and stderr to file (UNUSED)
+  RED_OUT_ERR,      // &>   Redirect stdout and stderr to file
 
-  RED_ERR,          // 2>   Redirect stderr to file (UNUSED)
-  RED_ERR_APP,      // 2>>  Append stderr to file (UNUSED)
+  RED_ERR,          // 2>   Redirect stderr to file
+  RED_ERR_APP,      // 2>>  Append stderr to file
 
   PIPE,             // |
 
@@ -62,80 +79,53 @@ pub struct CMD {
   pub right: Option<Arc<CMD>>,
 }
 
-extern {
-  fn get_type(addr: u64) -> u32;
-  fn get_argc(addr: u64) -> u32;
-  fn get_argv(addr: u64) -> u64;
-  fn get_nLocal(addr: u64) -> u32;
-  fn get_locVar(addr: u64) -> u64;
-  fn get_locVal(addr: u64) -> u64;
-  fn get_fromType(addr: u64) -> u32;
-  fn get_fromFile(addr: u64) -> u64;
-  fn get_toType(addr: u64) -> u32;
-  fn get_toFile(addr: u64) -> u64;
-  fn get_errType(addr: u64) -> u32;
-  fn get_errFile(addr: u64) -> u64;
-  fn get_left(addr: u64) -> u64;
-  fn get_right(addr: u64) -> u64;
-  fn get_cstr(addr: u64, idx: u32) -> u64;
+#[no_mangle]
+pub extern "C" fn init_shell() {
+  vars::record_startup();
+  jobs::init_job_control();
 }
 
-fn cstr2String(addr: u64) -> Option<String> {
-  if addr == 0 { return None; }
-  let c_buf: *const c_char = addr as *const c_char;
-  let c_str: &CStr = unsafe { CStr::from_ptr(c_buf) };
-  let str_slice: &str = c_str.to_str().unwrap();
-  Some(str_slice.to_owned())
+#[no_mangle]
+pub extern "C" fn set_arguments(argc: i32, raw_argv: u64) {
+  let argv = raw_argv as *const *const c_char;
+  let args = (0..argc.max(0) as usize)
+    .map(|i| unsafe { CStr::from_ptr(*argv.add(i)) }.to_string_lossy().into_owned())
+    .collect();
+  vars::set_arguments(args);
 }
 
-fn get_cstr_array(n: u32, addr: u64) -> Vec<Option<String>> {
-  let mut vec: Vec<Option<String>> = Vec::with_capacity(n as usize);
-  for i in 0..n {
-    vec.push(cstr2String(unsafe { get_cstr(addr, i) }));
-  }
-  vec
+// The next line from INPUT (a FILE *), without its newline
+fn read_line(input: u64) -> Option<String> {
+  let mut buf: *mut c_char = std::ptr::null_mut();
+  let mut size: libc::size_t = 0;
+  let len = unsafe { libc::getline(&mut buf, &mut size, input as *mut libc::FILE) };
+  let line = if len < 0 {
+    None
+  } else {
+    let bytes = unsafe { std::slice::from_raw_parts(buf as *const u8, len as usize) };
+    Some(String::from_utf8_lossy(bytes).trim_end_matches('\n').to_string())
+  };
+  unsafe { libc::free(buf as *mut libc::c_void) };
+  line
 }
 
-fn translate(raw_CMD: u64) -> Option<Arc<CMD>> {
-  if raw_CMD == 0 { return None; }
-  let node = unsafe { get_type(raw_CMD) };
-  let argc = unsafe { get_argc(raw_CMD) };
-  let argv = get_cstr_array(argc, unsafe { get_argv(raw_CMD) });
-  let nLocal = unsafe { get_nLocal(raw_CMD) };
-  let locVar = get_cstr_array(nLocal, unsafe { get_locVar(raw_CMD) });
-  let locVal = get_cstr_array(nLocal, unsafe { get_locVal(raw_CMD) });
-  let fromType = unsafe { get_fromType(raw_CMD) };
-  let fromFile = cstr2String(unsafe { get_fromFile(raw_CMD) });
-  let toType = unsafe { get_toType(raw_CMD) };
-  let toFile = cstr2String(unsafe { get_toFile(raw_CMD) });
-  let errType = unsafe { get_errType(raw_CMD) };
-  let errFile = cstr2String(unsafe { get_errFile(raw_CMD) });
-  let left = translate(unsafe { get_left(raw_CMD) });
-  let right = translate(unsafe { get_right(raw_CMD) });
-  Some(Arc::new(CMD {
-    node: node,
-    argc: argc,
-    argv: argv,
-    nLocal: nLocal,
-    locVar: locVar,
-    locVal: locVal,
-    fromType: fromType,
-    fromFile: fromFile,
-    toType: toType,
-    toFile: toFile,
-    errType: errType,
-    errFile: errFile,
-    left: left,
-    right: right,
-  }))
+#[no_mangle]
+pub extern "C" fn run_line(raw_line: u64, input: u64) -> i32 {
+  let line = unsafe { CStr::from_ptr(raw_line as *const c_char) }.to_string_lossy().into_owned();
+  dynamic::set_line_number(dynamic::line_number() + 1);
+  // lines read for a here document count too
+  let mut more = || {
+    let line = read_line(input)?;
+    dynamic::set_line_number(dynamic::line_number() + 1);
+    Some(line)
+  };
+  match parse::parse_line(line.trim_end_matches('\n'), &mut more) {
+    Some(CMD) => r_process(CMD) as i32,
+    None => -1,
+  }
 }
 
 #[no_mangle]
-pub extern fn process(raw_CMD: u64) -> u32 {
-  if let Some(CMD) = translate(raw_CMD) {
-    r_process(CMD)
-  }
-  else {
-    0
-  }
+pub extern "C" fn reap_jobs() {
+  jobs::reap_jobs();
 }
@@ -0,0 +1,130 @@
+use crate::builtins::BuiltinIo;
+use crate::vars::set_positional;
+use std::cell::RefCell;
+
+// Options understood by set -o / set +o
+static OPTION_NAMES: [&str; 1] = [
+    "pipefail",
+];
+
+// Options understood by shopt -s / shopt -u
+static SHOPT_NAMES: [&str; 1] = [
+    "failglob",
+];
+
+thread_local! {
+    static ENABLED: RefCell<Vec<&'static str>> = const { RefCell::new(Vec::new()) };
+}
+
+pub fn option_enabled(name: &str) -> bool {
+    ENABLED.with(|enabled| enabled.borrow().contains(&name))
+}
+
+fn set_option(names: &[&'static str], name: &str, on: bool) -> Result<(), ()> {
+    let name = match names.iter().find(|&&known| known == name) {
+        Some(&known) => known,
+        None => return Err(()),
+    };
+    ENABLED.with(|enabled| {
+        let mut enabled = enabled.borrow_mut();
+        enabled.retain(|&n| n != name);
+        if on {
+            enabled.push(name);
+        }
+    });
+    Ok(())
+}
+
+// set -o [name] / set +o [name]; with no name, list the options (set +o
+// lists them as commands that recreate the current settings).  Any other
+// arguments, or those after --, replace the positional parameters.
+pub fn process_set(argv: &[String], io: &mut BuiltinIo) -> u32 {
+    let args: Vec<&str> = argv.iter().skip(1).map(|a| a.as_str()).collect();
+    if args.is_empty() {
+        return 0;
+    }
+    let mut status = 0;
+    let mut i = 0;
+    while i < args.len() {
+        let on = match args[i] {
+            "-o" => true,
+            "+o" => false,
+            // the remaining arguments become the positional parameters
+            "--" => {
+                set_positional(args[i + 1..].iter().map(|a| a.to_string()).collect());
+                return status;
+            },
+            arg if !arg.starts_with(['-', '+']) => {
+                set_positional(args[i..].iter().map(|a| a.to_string()).collect());
+                return status;
+            },
+            arg => {
+                let _ = writeln!(io.stderr, "set: {}: invalid option", arg);
+                let _ = writeln!(io.stderr, "usage: set [-o|+o] [option] [--] [arg ...]");
+                return 2;
+            }
+        };
+        match args.get(i + 1) {
+            Some(name) => {
+                if set_option(&OPTION_NAMES, name, on).is_err() {
+                    let _ = writeln!(io.stderr, "set: {}: invalid option name", name);
+                    status = 1;
+                }
+                i += 2;
+            },
+            None => {
+                for name in OPTION_NAMES {
+                    let state = option_enabled(name);
+                    if on {
+                        let _ = writeln!(io.stdout, "{:<15}{}", name, if state { "on" } else { "off" });
+                    } else {
+                        let _ = writeln!(io.stdout, "set {}o {}", if state { "-" } else { "+" }, name);
+                    }
+                }
+                i += 1;
+            }
+        }
+    }
+    status
+}
+
+// shopt [-s|-u] [name ...]: -s turns the options on, -u off; with neither,
+// report them (status 1 if a named one is off)
+pub fn process_shopt(argv: &[String], io: &mut BuiltinIo) -> u32 {
+    let mut args: Vec<&str> = argv.iter().skip(1).map(|a| a.as_str()).collect();
+    let on = match args.first() {
+        Some(&"-s") => Some(true),
+        Some(&"-u") => Some(false),
+        Some(arg) if arg.starts_with('-') => {
+            let _ = writeln!(io.stderr, "shopt: {}: invalid option", arg);
+            let _ = writeln!(io.stderr, "usage: shopt [-s|-u] [optname ...]");
+            return 2;
+        },
+        _ => None,
+    };
+    if on.is_some() {
+        args.remove(0);
+    }
+    let listed: Vec<&str> = if args.is_empty() { SHOPT_NAMES.to_vec() } else { args };
+    let mut status = 0;
+    for name in listed {
+        if !SHOPT_NAMES.contains(&name) {
+            let _ = writeln!(io.stderr, "shopt: {}: invalid shell option name", name);
+            status = 1;
+            continue;
+        }
+        match on {
+            Some(on) => {
+                let _ = set_option(&SHOPT_NAMES, name, on);
+            },
+            None => {
+                let state = option_enabled(name);
+                let _ = writeln!(io.stdout, "{:<15}{}", name, if state { "on" } else { "off" });
+                if !state && argv.len() > 1 {
+                    status = 1;
+                }
+            }
+        }
+    }
+    status
+}
@@ -0,0 +1,665 @@
+// Lexer and parser for command lines, building the CMD tree that parse.h
+// describes.  Unlike the C tokenizer, words keep their quotes and
+// backslashes: the executor needs them to tell quoted text from unquoted
+// when it expands the word, and removes them itself.
+
+use std::collections::VecDeque;
+use crate::*;
+use crate::assign::assignment_word;
+use crate::vars::is_name;
+
+// Operators, longest first so that >> is not read as > >
+static OPERATORS: [(&str, u32); 14] = [
+    ("2>>", Type::RED_ERR_APP as u32),
+    ("2>", Type::RED_ERR as u32),
+    ("&>", Type::RED_OUT_ERR as u32),
+    ("<<", Type::RED_IN_HERE as u32),
+    (">>", Type::RED_OUT_APP as u32),
+    ("&&", Type::SEP_AND as u32),
+    ("||", Type::SEP_OR as u32),
+    ("<", Type::RED_IN as u32),
+    (">", Type::RED_OUT as u32),
+    ("&", Type::SEP_BG as u32),
+    ("|", Type::PIPE as u32),
+    (";", Type::SEP_END as u32),
+    ("(", Type::PAR_LEFT as u32),
+    (")", Type::PAR_RIGHT as u32),
+];
+
+// Characters that end an unquoted word
+static METACHARS: &str = "<>;&|()";
+
+#[derive(Clone)]
+enum Token {
+    Word(String),
+    Op(u32),
+}
+
+struct Lexer<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    // the lines after the first, for here documents and for quotes that
+    // are still open at the end of a line
+    more: &'a mut dyn FnMut() -> Option<String>,
+}
+
+impl Lexer<'_> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    // The next character, reading on into the next line if this one ends
+    // before the CLOSE that would end the quote being read
+    fn next(&mut self, close: char) -> Result<char, String> {
+        if self.pos == self.chars.len() {
+            let line = (self.more)()
+                .ok_or_else(|| format!("unexpected EOF while looking for matching `{}'", close))?;
+            self.chars.push('\n');
+            self.chars.extend(line.chars());
+        }
+        self.pos += 1;
+        Ok(self.chars[self.pos - 1])
+    }
+
+    // The operator at the current position, if there is one, skipping it
+    fn operator(&mut self) -> Option<u32> {
+        let rest: String = self.chars[self.pos..].iter().take(3).collect();
+        let &(text, op) = OPERATORS.iter().find(|(text, _)| rest.starts_with(text))?;
+        self.pos += text.len();
+        Some(op)
+    }
+
+    // The words and operators on the line, and the here document
+    // delimiters among the words, in order
+    fn tokens(&mut self) -> Result<(Vec<Token>, Vec<String>), String> {
+        let mut tokens = Vec::new();
+        let mut delimiters = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == ' ' || c == '\t' || c == '\n' {
+                self.pos += 1;
+                continue;
+            }
+            // a comment runs to the end of the line
+            if c == '#' {
+                break;
+            }
+            if let Some(op) = self.operator() {
+                tokens.push(Token::Op(op));
+                continue;
+            }
+            let word = self.word()?;
+            // all there was was a \ joining the next line on
+            if word.is_empty() {
+                continue;
+            }
+            if let Some(Token::Op(op)) = tokens.last() {
+                if *op == Type::RED_IN_HERE as u32 {
+                    delimiters.push(word.clone());
+                }
+            }
+            tokens.push(Token::Word(word));
+        }
+        Ok((tokens, delimiters))
+    }
+
+    // The word at the current position, quotes and all
+    fn word(&mut self) -> Result<String, String> {
+        let mut word = String::new();
+        while let Some(c) = self.peek() {
+            // NAME=( starts a compound assignment, which runs to the )
+            if c == '(' && word.ends_with('=') && assignment_word(&word).is_some() {
+                self.pos += 1;
+                word.push(c);
+                self.compound(&mut word)?;
+                continue;
+            }
+            if c == ' ' || c == '\t' || c == '\n' || METACHARS.contains(c) {
+                break;
+            }
+            self.pos += 1;
+            match c {
+                '\\' if self.pos == self.chars.len() => {
+                    // \ at the end of the line joins the next line on
+                    match (self.more)() {
+                        Some(line) => self.chars.extend(line.chars()),
+                        None => word.push(c),
+                    }
+                },
+                '\\' => {
+                    word.push(c);
+                    word.push(self.next('\\')?);
+                },
+                '\'' => {
+                    word.push(c);
+                    self.single_quoted(&mut word)?;
+                },
+                '"' => {
+                    word.push(c);
+                    self.double_quoted(&mut word)?;
+                },
+                '$' if self.peek() == Some('\'') => {
+                    word.push(c);
+                    word.push(self.next('\'')?);
+                    self.ansi_quoted(&mut word)?;
+                },
+                '$' => {
+                    word.push(c);
+                    self.dollar(&mut word)?;
+                },
+                '`' => {
+                    word.push(c);
+                    self.backquoted(&mut word)?;
+                },
+                _ => word.push(c),
+            }
+        }
+        Ok(word)
+    }
+
+    // The rest of '...', onto WORD
+    fn single_quoted(&mut self, word: &mut String) -> Result<(), String> {
+        loop {
+            let c = self.next('\'')?;
+            word.push(c);
+            if c == '\'' {
+                return Ok(());
+            }
+        }
+    }
+
+    // The rest of $'...', where \' does not end it, onto WORD
+    fn ansi_quoted(&mut self, word: &mut String) -> Result<(), String> {
+        loop {
+            let c = self.next('\'')?;
+            word.push(c);
+            match c {
+                '\'' => return Ok(()),
+                '\\' => word.push(self.next('\'')?),
+                _ => (),
+            }
+        }
+    }
+
+    // The rest of "...", onto WORD
+    fn double_quoted(&mut self, word: &mut String) -> Result<(), String> {
+        loop {
+            let c = self.next('"')?;
+            word.push(c);
+            match c {
+                '"' => return Ok(()),
+                '\\' => word.push(self.next('"')?),
+                '$' => self.dollar(word)?,
+                '`' => self.backquoted(word)?,
+                _ => (),
+            }
+        }
+    }
+
+    // The rest of the (word ...) of NAME=(word ...), onto WORD; the words
+    // may be spread over several lines
+    fn compound(&mut self, word: &mut String) -> Result<(), String> {
+        loop {
+            let c = self.next(')')?;
+            word.push(c);
+            match c {
+                ')' => return Ok(()),
+                '\\' => word.push(self.next(')')?),
+                '\'' => self.single_quoted(word)?,
+                '"' => self.double_quoted(word)?,
+                '`' => self.backquoted(word)?,
+                '$' => self.dollar(word)?,
+                _ => (),
+            }
+        }
+    }
+
+    // The rest of `...`, onto WORD
+    fn backquoted(&mut self, word: &mut String) -> Result<(), String> {
+        loop {
+            let c = self.next('`')?;
+            word.push(c);
+            match c {
+                '`' => return Ok(()),
+                '\\' => word.push(self.next('`')?),
+                _ => (),
+            }
+        }
+    }
+
+    // What follows a $ that has just been read onto WORD: all of ${...},
+    // $(...) or $((...)), whatever they contain
+    fn dollar(&mut self, word: &mut String) -> Result<(), String> {
+        if self.peek() == Some('(') {
+            self.pos += 1;
+            word.push('(');
+            // the ( of $(( counts as one more level to close
+            let mut depth = 0;
+            loop {
+                let c = self.next(')')?;
+                word.push(c);
+                match c {
+                    '(' => depth += 1,
+                    ')' if depth == 0 => break,
+                    ')' => depth -= 1,
+                    '\\' => word.push(self.next(')')?),
+                    '\'' => self.single_quoted(word)?,
+                    '"' => self.double_quoted(word)?,
+                    '`' => self.backquoted(word)?,
+                    '$' => self.dollar(word)?,
+                    _ => (),
+                }
+            }
+        } else if self.peek() == Some('{') {
+            self.pos += 1;
+            word.push('{');
+            loop {
+                let c = self.next('}')?;
+                word.push(c);
+                match c {
+                    '}' => break,
+                    '\\' => word.push(self.next('}')?),
+                    '\'' => self.single_quoted(word)?,
+                    '"' => self.double_quoted(word)?,
+                    '`' => self.backquoted(word)?,
+                    '$' => self.dollar(word)?,
+                    _ => (),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // The lines of a here document up to (not including) DELIMITER.  With
+    // any part of DELIMITER quoted, the text is taken literally, which is
+    // arranged by escaping what expansion would otherwise act on.
+    fn here_document(&mut self, delimiter: &str) -> String {
+        let end = unquote(delimiter);
+        let literal = delimiter.contains(['\'', '"', '\\']);
+        let mut text = String::new();
+        while let Some(line) = (self.more)() {
+            if line == end {
+                break;
+            }
+            for c in line.chars() {
+                if literal && "\\$`".contains(c) {
+                    text.push('\\');
+                }
+                text.push(c);
+            }
+            text.push('\n');
+        }
+        text
+    }
+}
+
+// WORD with its quotes and backslashes removed
+fn unquote(word: &str) -> String {
+    let mut text = String::new();
+    let mut quote = None;
+    let mut chars = word.chars();
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\\', None) | ('\\', Some('"')) => text.extend(chars.next()),
+            ('\'' | '"', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            (c, _) => text.push(c),
+        }
+    }
+    text
+}
+
+fn is_redirect(op: u32) -> bool {
+    op == Type::RED_IN as u32 || op == Type::RED_IN_HERE as u32
+        || op == Type::RED_OUT as u32 || op == Type::RED_OUT_APP as u32
+        || op == Type::RED_ERR as u32 || op == Type::RED_ERR_APP as u32
+        || op == Type::RED_OUT_ERR as u32
+}
+
+// NAME=value as NAME and the value, for a local variable
+fn local(word: &str) -> Option<(String, String)> {
+    let (name, value) = word.split_once('=')?;
+    if !is_name(name) {
+        return None;
+    }
+    Some((name.to_string(), value.to_string()))
+}
+
+// An empty command structure of type NODE, as mallocCMD() makes
+fn new_cmd(node: u32) -> CMD {
+    CMD {
+        node,
+        argc: 0,
+        argv: Vec::new(),
+        nLocal: 0,
+        locVar: Vec::new(),
+        locVal: Vec::new(),
+        fromType: Type::NONE as u32,
+        fromFile: None,
+        toType: Type::NONE as u32,
+        toFile: None,
+        errType: Type::NONE as u32,
+        errFile: None,
+        left: None,
+        right: None,
+    }
+}
+
+fn new_node(node: u32, left: Arc<CMD>, right: Option<Arc<CMD>>) -> Arc<CMD> {
+    let mut cmd = new_cmd(node);
+    cmd.left = Some(left);
+    cmd.right = right;
+    Arc::new(cmd)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    // the text of each here document on the line, in order
+    heres: VecDeque<String>,
+}
+
+impl Parser {
+    fn peek_op(&self) -> Option<u32> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) => Some(*op),
+            _ => None,
+        }
+    }
+
+    // <command>: <and-or>s separated by ; or &, perhaps ending with one
+    fn command(&mut self) -> Result<Arc<CMD>, String> {
+        let mut cmd = self.and_or()?;
+        while let Some(op) = self.peek_op() {
+            if op != Type::SEP_END as u32 && op != Type::SEP_BG as u32 {
+                break;
+            }
+            self.pos += 1;
+            if self.pos == self.tokens.len() || self.peek_op() == Some(Type::PAR_RIGHT as u32) {
+                return Ok(new_node(op, cmd, None));
+            }
+            let right = self.and_or()?;
+            cmd = new_node(op, cmd, Some(right));
+        }
+        Ok(cmd)
+    }
+
+    // <and-or>: <pipeline>s separated by && or ||
+    fn and_or(&mut self) -> Result<Arc<CMD>, String> {
+        let mut cmd = self.pipeline()?;
+        while let Some(op) = self.peek_op() {
+            if op != Type::SEP_AND as u32 && op != Type::SEP_OR as u32 {
+                break;
+            }
+            self.pos += 1;
+            let right = self.pipeline()?;
+            cmd = new_node(op, cmd, Some(right));
+        }
+        Ok(cmd)
+    }
+
+    // <pipeline>: <stage>s separated by |
+    fn pipeline(&mut self) -> Result<Arc<CMD>, String> {
+        let mut cmd = self.stage()?;
+        while self.peek_op() == Some(Type::PIPE as u32) {
+            self.pos += 1;
+            let right = self.stage()?;
+            cmd = new_node(Type::PIPE as u32, cmd, Some(right));
+        }
+        Ok(cmd)
+    }
+
+    // <stage>: a simple command, or a (subcommand), after a prefix of
+    // locals and redirections; the prefix alone is a command too
+    fn stage(&mut self) -> Result<Arc<CMD>, String> {
+        let mut cmd = new_cmd(Type::SIMPLE as u32);
+        loop {
+            match self.tokens.get(self.pos).cloned() {
+                Some(Token::Word(word)) => {
+                    self.pos += 1;
+                    match local(&word) {
+                        Some((name, value)) if cmd.argv.is_empty() => {
+                            cmd.locVar.push(Some(name));
+                            cmd.locVal.push(Some(value));
+                        },
+                        _ => cmd.argv.push(Some(word)),
+                    }
+                },
+                Some(Token::Op(op)) if is_redirect(op) => {
+                    self.pos += 1;
+                    self.redirect(op, &mut cmd)?;
+                },
+                Some(Token::Op(op)) if op == Type::PAR_LEFT as u32 => {
+                    if !cmd.argv.is_empty() {
+                        return Err(String::from("command and subcommand"));
+                    }
+                    self.pos += 1;
+                    return self.subcommand(cmd);
+                },
+                _ => break,
+            }
+        }
+        // a stage may be just assignments or redirections, as in "X=5" or
+        // "> file", but not nothing at all
+        let redirected = cmd.fromType != Type::NONE as u32 || cmd.toType != Type::NONE as u32
+            || cmd.errType != Type::NONE as u32;
+        if cmd.argv.is_empty() && cmd.locVar.is_empty() && !redirected {
+            return Err(String::from("null command"));
+        }
+        cmd.argc = cmd.argv.len() as u32;
+        cmd.nLocal = cmd.locVar.len() as u32;
+        Ok(Arc::new(cmd))
+    }
+
+    // The rest of a <subcmd> after its (, CMD holding the prefix
+    fn subcommand(&mut self, mut cmd: CMD) -> Result<Arc<CMD>, String> {
+        let inner = self.command()?;
+        if self.peek_op() != Some(Type::PAR_RIGHT as u32) {
+            return Err(String::from("unbalanced parentheses"));
+        }
+        self.pos += 1;
+        while let Some(op) = self.peek_op().filter(|&op| is_redirect(op)) {
+            self.pos += 1;
+            self.redirect(op, &mut cmd)?;
+        }
+        match self.tokens.get(self.pos) {
+            Some(Token::Word(_)) => return Err(String::from("command and subcommand")),
+            Some(Token::Op(op)) if *op == Type::PAR_LEFT as u32 => {
+                return Err(String::from("two subcommands"));
+            },
+            _ => (),
+        }
+        cmd.node = Type::SUBCMD as u32;
+        cmd.nLocal = cmd.locVar.len() as u32;
+        cmd.left = Some(inner);
+        Ok(Arc::new(cmd))
+    }
+
+    // The filename after redirection operator OP, recorded in CMD.  &> is
+    // both toType and errType, with the file in toFile only.
+    fn redirect(&mut self, op: u32, cmd: &mut CMD) -> Result<(), String> {
+        let file = match self.tokens.get(self.pos) {
+            Some(Token::Word(file)) => file.clone(),
+            _ => return Err(String::from("missing filename")),
+        };
+        self.pos += 1;
+        if op == Type::RED_IN as u32 || op == Type::RED_IN_HERE as u32 {
+            if cmd.fromType != Type::NONE as u32 {
+                return Err(String::from("two input redirects"));
+            }
+            cmd.fromType = op;
+            cmd.fromFile = if op == Type::RED_IN_HERE as u32 { self.heres.pop_front() } else { Some(file) };
+        } else if op == Type::RED_ERR as u32 || op == Type::RED_ERR_APP as u32 {
+            if cmd.errType != Type::NONE as u32 {
+                return Err(String::from("two error redirects"));
+            }
+            cmd.errType = op;
+            cmd.errFile = Some(file);
+        } else {
+            if cmd.toType != Type::NONE as u32 {
+                return Err(String::from("two output redirects"));
+            }
+            if op == Type::RED_OUT_ERR as u32 {
+                if cmd.errType != Type::NONE as u32 {
+                    return Err(String::from("two error redirects"));
+                }
+                cmd.errType = op;
+            }
+            cmd.toType = op;
+            cmd.toFile = Some(file);
+        }
+        Ok(())
+    }
+}
+
+// Parse LINE, taking any more lines it needs (for here documents, and
+// quotes left open at the end of a line) from MORE.  None if there is no
+// command on it, or it does not parse (and the error has been reported).
+pub fn parse_line(line: &str, more: &mut dyn FnMut() -> Option<String>) -> Option<Arc<CMD>> {
+    let mut lexer = Lexer { chars: line.chars().collect(), pos: 0, more };
+    let result = lexer.tokens().and_then(|(tokens, delimiters)| {
+        // the here documents follow the line that asks for them
+        let heres = delimiters.iter().map(|delimiter| lexer.here_document(delimiter)).collect();
+        if tokens.is_empty() {
+            return Ok(None);
+        }
+        let mut parser = Parser { tokens, pos: 0, heres };
+        let cmd = parser.command()?;
+        if parser.pos < parser.tokens.len() {
+            return Err(String::from(match parser.peek_op() {
+                Some(op) if op == Type::PAR_RIGHT as u32 => "unbalanced parentheses",
+                _ => "unexpected token",
+            }));
+        }
+        Ok(Some(cmd))
+    });
+    match result {
+        Ok(cmd) => cmd,
+        Err(message) => {
+            eprintln!("Parse: {}", message);
+            None
+        }
+    }
+}
+
+// The words in TEXT, quotes and all, as between the parentheses of
+// NAME=(word ...); None if it holds an operator or an unclosed quote
+pub fn words(text: &str) -> Option<Vec<String>> {
+    let mut lexer = Lexer { chars: text.chars().collect(), pos: 0, more: &mut || None };
+    let (tokens, _) = lexer.tokens().ok()?;
+    tokens.into_iter()
+        .map(|token| match token {
+            Token::Word(word) => Some(word),
+            Token::Op(_) => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Option<Arc<CMD>> {
+        parse_line(line, &mut || None)
+    }
+
+    fn args(cmd: &CMD) -> Vec<&str> {
+        cmd.argv.iter().flatten().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn words_keep_their_quotes() {
+        let cmd = parse("echo 'a b' \"$x y\" a\\ b $'c\\'d' $(echo e f) `g h` ${i:-j k}").unwrap();
+        assert_eq!(args(&cmd), ["echo", "'a b'", "\"$x y\"", "a\\ b", "$'c\\'d'", "$(echo e f)", "`g h`", "${i:-j k}"]);
+    }
+
+    #[test]
+    fn substitutions_nest() {
+        let cmd = parse("echo $(echo \"$(echo ')')\") $((1 + (2 * 3)))").unwrap();
+        assert_eq!(args(&cmd), ["echo", "$(echo \"$(echo ')')\")", "$((1 + (2 * 3)))"]);
+    }
+
+    #[test]
+    fn locals_only_before_the_command() {
+        let cmd = parse("A=1 B='x y' cmd C=2").unwrap();
+        assert_eq!(cmd.nLocal, 2);
+        assert_eq!(cmd.locVal[1].as_deref(), Some("'x y'"));
+        assert_eq!(args(&cmd), ["cmd", "C=2"]);
+    }
+
+    #[test]
+    fn tree_shapes() {
+        // A ; B & C ; D || E ;  is  ;(;(&(;(A, B), C), ||(D, E)), NULL)
+        let cmd = parse("A ; B & C ; D || E ;").unwrap();
+        assert_eq!(cmd.node, Type::SEP_END as u32);
+        assert!(cmd.right.is_none());
+        let seq = cmd.left.as_ref().unwrap();
+        assert_eq!(seq.right.as_ref().unwrap().node, Type::SEP_OR as u32);
+        assert_eq!(seq.left.as_ref().unwrap().node, Type::SEP_BG as u32);
+        let pipe = parse("< f a | b | c > g").unwrap();
+        assert_eq!(pipe.node, Type::PIPE as u32);
+        assert_eq!(pipe.right.as_ref().unwrap().toFile.as_deref(), Some("g"));
+        assert_eq!(pipe.left.as_ref().unwrap().left.as_ref().unwrap().fromFile.as_deref(), Some("f"));
+    }
+
+    #[test]
+    fn subcommands() {
+        let cmd = parse("X=1 (a; b) > f").unwrap();
+        assert_eq!(cmd.node, Type::SUBCMD as u32);
+        assert_eq!(cmd.nLocal, 1);
+        assert_eq!(cmd.toFile.as_deref(), Some("f"));
+        assert_eq!(cmd.left.as_ref().unwrap().node, Type::SEP_END as u32);
+    }
+
+    #[test]
+    fn stderr_redirections() {
+        let cmd = parse("a 2>> e").unwrap();
+        assert_eq!((cmd.errType, cmd.errFile.as_deref()), (Type::RED_ERR_APP as u32, Some("e")));
+        let cmd = parse("a &> f").unwrap();
+        assert_eq!((cmd.toType, cmd.errType), (Type::RED_OUT_ERR as u32, Type::RED_OUT_ERR as u32));
+        assert_eq!((cmd.toFile.as_deref(), cmd.errFile.as_deref()), (Some("f"), None));
+        assert_eq!(args(&parse("a2>f").unwrap()), ["a2"]);
+    }
+
+    #[test]
+    fn prefix_alone_is_a_command() {
+        let cmd = parse("X=5").unwrap();
+        assert_eq!((cmd.argc, cmd.nLocal), (0, 1));
+        let cmd = parse("> f").unwrap();
+        assert_eq!((cmd.argc, cmd.toFile.as_deref()), (0, Some("f")));
+    }
+
+    #[test]
+    fn errors() {
+        for line in ["a ;; b", "a |", "(a) b", "a (b)", "(a)(b)", "(a", "a)", "a >", "a < f < g", "a 2>e 2>f", "'a"] {
+            assert!(parse(line).is_none(), "{}", line);
+        }
+        assert!(parse("   # just a comment").is_none());
+    }
+
+    #[test]
+    fn more_lines() {
+        let mut lines = vec!["b\"", "x $y", "E", "after"].into_iter().map(String::from);
+        let cmd = parse_line("cat <<E \"a", &mut || lines.next()).unwrap();
+        assert_eq!(args(&cmd), ["cat", "\"a\nb\""]);
+        assert_eq!(cmd.fromFile.as_deref(), Some("x $y\n"));
+        assert_eq!(lines.next().as_deref(), Some("after"));
+        let mut lines = vec!["$y `z` \\".to_string()].into_iter();
+        let cmd = parse_line("cat <<'E'", &mut || lines.next()).unwrap();
+        assert_eq!(cmd.fromFile.as_deref(), Some("\\$y \\`z\\` \\\\\n"));
+    }
+
+    #[test]
+    fn compound_assignments() {
+        let cmd = parse("arr=() b=(x \"y )\" $(echo ')') [k]=v) c=d (e)").unwrap();
+        assert_eq!(cmd.node, Type::SUBCMD as u32);
+        assert_eq!(cmd.locVal.iter().flatten().collect::<Vec<_>>(), ["()", "(x \"y )\" $(echo ')') [k]=v)", "d"]);
+        let cmd = parse("declare -A m=([k]=v)").unwrap();
+        assert_eq!(args(&cmd), ["declare", "-A", "m=([k]=v)"]);
+        // only straight after NAME=
+        assert!(parse("echo =(x)").is_none());
+        assert!(parse("arr=(a").is_none());
+        assert_eq!(words("a 'b c'\n[k]=\"v\""), Some(vec![String::from("a"), String::from("'b c'"), String::from("[k]=\"v\"")]));
+        assert_eq!(words("a | b"), None);
+    }
+}
+
@@ -0,0 +1,177 @@
+// Shell pattern matching (*, ?, [...]) as used by ${VAR#pattern} and
+// filename globbing.  A backslash makes the next character match only itself.
+
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    match_from(&pattern, &text)
+}
+
+// true if PATTERN contains an unescaped *, ? or [
+pub fn has_magic(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            },
+            '*' | '?' | '[' => return true,
+            _ => (),
+        }
+    }
+    false
+}
+
+// PATTERN with its backslash escapes removed
+pub fn unescape(pattern: &str) -> String {
+    let mut out = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(next) = chars.next() {
+                    out.push(next);
+                }
+            },
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn match_from(pattern: &[char], text: &[char]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // where the last * was and how much text it has taken so far
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        let step = if p < pattern.len() { match_one(pattern, p, text[t]) } else { None };
+        match step {
+            _ if p < pattern.len() && pattern[p] == '*' => {
+                star = Some((p, t));
+                p += 1;
+            },
+            Some(next) => {
+                p = next;
+                t += 1;
+            },
+            None => match star {
+                // let the last * take one more character and retry
+                Some((sp, st)) => {
+                    star = Some((sp, st + 1));
+                    p = sp + 1;
+                    t = st + 1;
+                },
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// If the pattern element at P matches C, the index just past it
+fn match_one(pattern: &[char], p: usize, c: char) -> Option<usize> {
+    match pattern[p] {
+        '?' => Some(p + 1),
+        '\\' if p + 1 < pattern.len() => {
+            if pattern[p + 1] == c { Some(p + 2) } else { None }
+        },
+        '[' => match match_class(pattern, p, c) {
+            Some((true, next)) => Some(next),
+            Some((false, _)) => None,
+            // no closing ]: the [ is an ordinary character
+            None => if c == '[' { Some(p + 1) } else { None },
+        },
+        literal => if literal == c { Some(p + 1) } else { None },
+    }
+}
+
+// Match C against the bracket expression starting at P; returns whether it
+// matched and the index past the closing ], or None if there is no ]
+fn match_class(pattern: &[char], p: usize, c: char) -> Option<(bool, usize)> {
+    let mut i = p + 1;
+    let negate = i < pattern.len() && (pattern[i] == '!' || pattern[i] == '^');
+    if negate {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    while i < pattern.len() {
+        if pattern[i] == ']' && !first {
+            return Some((matched != negate, i + 1));
+        }
+        first = false;
+        let mut lo = pattern[i];
+        if lo == '\\' && i + 1 < pattern.len() {
+            i += 1;
+            lo = pattern[i];
+        }
+        if i + 2 < pattern.len() && pattern[i + 1] == '-' && pattern[i + 2] != ']' {
+            let mut hi = pattern[i + 2];
+            i += 2;
+            if hi == '\\' && i + 1 < pattern.len() {
+                i += 1;
+                hi = pattern[i];
+            }
+            if lo <= c && c <= hi {
+                matched = true;
+            }
+        } else if lo == c {
+            matched = true;
+        }
+        i += 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(!matches("*.rs", "main.c"));
+        assert!(matches("a?c", "abc"));
+        assert!(!matches("a?c", "ac"));
+        assert!(matches("*", ""));
+        assert!(matches("a*b*c", "aXbYbc"));
+    }
+
+    #[test]
+    fn bracket_first_close_is_a_member() {
+        assert!(matches("[]a]", "]"));
+        assert!(matches("[]a]", "a"));
+        assert!(!matches("[]a]", "b"));
+    }
+
+    #[test]
+    fn negated_bracket() {
+        assert!(!matches("[!]]", "]"));
+        assert!(matches("[!]]", "x"));
+        assert!(matches("[^a-c]", "d"));
+        assert!(!matches("[^a-c]", "b"));
+    }
+
+    #[test]
+    fn ranges_and_escapes() {
+        assert!(matches("[a-c]x", "bx"));
+        assert!(matches("[a-]", "-"));
+        assert!(matches("[\\]]", "]"));
+        assert!(matches("a\\*", "a*"));
+        assert!(!matches("a\\*", "ab"));
+    }
+
+    #[test]
+    fn unclosed_bracket_is_literal() {
+        assert!(matches("[ab", "[ab"));
+        assert!(!matches("[ab", "a"));
+    }
+
+    #[test]
+    fn magic_and_unescape() {
+        assert!(has_magic("a*"));
+        assert!(has_magic("[x]"));
+        assert!(!has_magic("a\\*b"));
+        assert_eq!(unescape("a\\*b\\\\"), "a*b\\");
+    }
+}
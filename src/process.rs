@@ -1,70 +1,58 @@
-use std::ffi::{c_void, CString};
-use std::path::PathBuf;
+use std::ffi::CString;
 use nix::errno::Errno;
-use std::os::raw::c_char;
 use crate::*;
-use libc::{ setenv, unlink, EXIT_FAILURE, O_APPEND, O_CREAT, O_RDONLY, O_TRUNC, O_WRONLY, STDIN_FILENO, STDOUT_FILENO};
-use nix::sys::wait::{self, WaitPidFlag, WaitStatus};
-use nix::unistd::{chdir, execvp, fork, getcwd, pipe, ForkResult, Pid};
-use std::cell::RefCell;
-
-thread_local! {
-    static DIR_STACK: RefCell<Vec<PathBuf>> = RefCell::new(Vec::new());
-}
+use crate::jobs::*;
+use crate::expand::{expand_assignment, expand_cmd, substitution_status};
+use crate::assign::{assign_raw, assign_words};
+use crate::builtins::{find_builtin, Builtin, BuiltinIo, FdStdin};
+use crate::options::option_enabled;
+use crate::redirect::{handle_redirection, restore_std_fds, save_std_fds};
+use crate::vars::{environment, get_var, pop_locals, push_locals, set_last_background, set_last_status, set_local, set_value, Value};
+use libc::{EXIT_FAILURE, STDIN_FILENO, STDOUT_FILENO};
+use nix::sys::wait::WaitStatus;
+use nix::unistd::{execve, fork, pipe, ForkResult, Pid};
+use std::rc::Rc;
 
 #[derive(Clone)]
-struct Entry {
-    pid: Pid,
-    status: WaitStatus,
+pub struct Entry {
+    pub pid: Pid,
+    pub status: WaitStatus,
 }
-static BUILT_INS: [&str; 3] = [
-    "pushd",
-    "popd",
-    "cd",
-];
-
 // use crate::syscall::*;
 pub fn r_process(_cmdList: Arc<CMD>) -> u32 {
     let exit_status = handle_any(&_cmdList);
-    match wait::waitpid(Pid::from_raw(-1), Some(WaitPidFlag::WNOHANG)) {
-        Ok(WaitStatus::Exited(pid, status)) => {
-            eprintln!("Completed: {} ({})", pid, status);
-        },
-        _ => {}
-    }
-    return exit_status;
+    jobs::reap_jobs();
+    exit_status
 }
 
 fn handle_any(_cmdList: &Arc<CMD>) -> u32 {
     let exit_status = match _cmdList.node {
         x if x == Type::SIMPLE as u32 => {
-            handle_simple(&_cmdList) as u32
+            handle_simple(_cmdList)
         },
         x if x == Type::PIPE as u32 => {
-            handle_pipe(&_cmdList) as u32
+            handle_pipe(_cmdList) as u32
         },
         x if x == Type::SEP_AND as u32 || x == Type::SEP_OR as u32 => {
-            handle_cond(&_cmdList) as u32
+            handle_cond(_cmdList)
         },
         x if x == Type::SEP_BG as u32 => {
-            handle_bg(&_cmdList) as u32
+            handle_bg(_cmdList)
         },
         x if x == Type::SEP_END as u32 => {
-            handle_sep_end(&_cmdList) as u32
+            handle_sep_end(_cmdList)
         },
         x if x == Type::SUBCMD as u32 => {
-            handle_subcmd(&_cmdList) as u32
+            handle_subcmd(_cmdList)
         },
         _ => 0
     };
-    let name_cstr = string2CStr("?");
-    let val_cstr = string2CStr(exit_status.to_string().as_str());
-    unsafe { setenv(name_cstr.as_ptr(), val_cstr.as_ptr(), 1); }
-    return exit_status;
+    set_last_status(exit_status);
+    exit_status
 }
 
-fn string2CStr (s: &str) -> CString {
-    return std::ffi::CString::new(s).unwrap();
+pub fn string2CStr (s: &str) -> CString {
+    std::ffi::CString::new(s).unwrap()
 }
 
 fn get_program_and_args(_cmdList: &Arc<CMD>) -> (CString, Vec<CString>) {
@@ -81,243 +69,105 @@ fn get_program_and_args(_cmdList: &Arc<CMD>) -> (CString, Vec<CString>) {
         unsafe { libc::_exit(1)};
     }
     // convert Option<String> items in args vector to CString
-    let args: Vec<std::ffi::CString> = _cmdList.argv.iter().filter_map(|arg| match &arg {
-        Some(a) => Some(string2CStr(a)), // String -> CString if some
-        None => None
+    let args: Vec<std::ffi::CString> = _cmdList.argv.iter().filter_map(|arg| {
+        arg.as_ref().map(|a| string2CStr(a)) // String -> CString if some
     }).collect(); // converts the op
-    return (program, args)
+    (program, args)
 }
 
+// Prefix assignments (NAME=value cmd) go into the command's environment.
+// In the shell itself (for a builtin) they are local, undone by the caller's
+// pop_locals(); in a child that is about to exec they can simply be set.
 fn handle_locals(_cmdList: &Arc<CMD>) -> Result<(), Errno>  {
     for n in 0.._cmdList.nLocal as usize {
         let (name, val) = match (_cmdList.locVar[n].as_ref(), _cmdList.locVal[n].as_ref()) {
             (Some(n), Some(v)) => (n, v),
             _ => break,
         };
-        let name_cstr = string2CStr(name);
-        let val_cstr = string2CStr(val);
-        unsafe {
-            if setenv(name_cstr.as_ptr(), val_cstr.as_ptr(), 1) < 0 {
-                return Err(Errno::last());
-            }
+        // a bad expansion or readonly variable has already been reported
+        let val = expand_assignment(val).map_err(|_| Errno::EPERM)?;
+        if set_local(name, &val).is_err() {
+            return Err(Errno::EPERM);
         }
     }
     Ok(())
 }
 
-fn cd_dir_name(dirName: &PathBuf) -> Result<(), Errno> {
-    match chdir(dirName) {
-        Ok(_) => {
-            let name_cstr = string2CStr("PWD");
-            let val_cstr = string2CStr(dirName.to_str().unwrap());
-            unsafe { setenv(name_cstr.as_ptr(), val_cstr.as_ptr(), 1); }
-        },
-        Err(_) => {
-            let err = std::ffi::CString::new("chdir failed").unwrap();
-            unsafe {
-                libc::perror(err.as_ptr());
-                return Err(Errno::ENOENT);
-            }
-        }
-    }
-    Ok(())
-}
-fn process_cd(_cmdList: &Arc<CMD>) -> u32 {
-    match _cmdList.argv.len() {
-        // "cd"
-        1 => {
-            let key = "HOME";
-            match std::env::var(key) {
-                Ok(path) => {
-                    let path_buf = PathBuf::from(path);
-                    // println!("path: {}", path);
-                    match chdir(path_buf.as_path()) {
-                        Ok(_) => {
-                            let name_cstr = string2CStr("PWD");
-                            let val_cstr = string2CStr(path_buf.to_str().unwrap());
-                            unsafe { setenv(name_cstr.as_ptr(), val_cstr.as_ptr(), 1); }
-                            0
-                        },
-                        Err(_) => {
-                            let err =std::ffi::CString::new("chdir failed").unwrap();
-                            unsafe {
-                                libc::perror(err.as_ptr());
-                            }
-                            return Errno::ENOENT as u32;
-                        }
-                    }
-                },
-                Err(_) => {
-                    let err = std::ffi::CString::new("undefined").unwrap();
-                    unsafe {
-                        libc::perror(err.as_ptr());
-                    }
-                    1
-                }
-            }
-        },
-        // "cd [dir]"
-        2 => {
-            if let Some(dir) = _cmdList.argv[1].as_ref() { 
-                if let Err(e) = cd_dir_name(&PathBuf::from(dir)) {
-                    return e as u32;
-                };
-            }
-            return 1;
-        },
-        _ => {
-            unsafe {
-                let err =std::ffi::CString::new("too many argument").unwrap();
-                libc::perror(err.as_ptr());
-            }
-            return 1;
+// execvp(), but searching the shell's own PATH and passing the exported
+// variables as the environment; only returns on failure
+fn exec_program(program: &CString, args: &[CString]) -> Errno {
+    let env = environment();
+    let name = program.to_string_lossy();
+    if name.contains('/') {
+        return execve(program, args, &env).unwrap_err();
+    }
+    let path = get_var("PATH").unwrap_or(String::from("/usr/local/bin:/usr/bin:/bin"));
+    let mut error = Errno::ENOENT;
+    for dir in path.split(':') {
+        let dir = if dir.is_empty() { "." } else { dir };
+        let full = string2CStr(&format!("{}/{}", dir, name));
+        match execve(&full, args, &env).unwrap_err() {
+            // keep looking, but report a file that exists over one that doesn't
+            Errno::ENOENT | Errno::ENOTDIR => (),
+            e => error = e,
         }
     }
-}
-
-fn print_dir_stack() -> Result<(), Errno> {
-    let newDir = match getcwd() {
-        Ok(path) => path,
-        Err(_) => {
-            let err = std::ffi::CString::new("failed to get cwd").unwrap();
-            unsafe {
-                libc::perror(err.as_ptr());
-            }
-            return Err(Errno::ENOENT);
-        }
-    };
-    print!("{} ", newDir.display());
-    DIR_STACK.with(|stack| {
-        let dir_stack = stack.borrow();
-        if dir_stack.len() == 1 {
-            print!("{}", dir_stack[0].display());
-        } else if dir_stack.len() > 1 {
-            for i in (1..dir_stack.len()).rev() {
-                print!("{} ", dir_stack[i].display());
-            }
-            print!("{}", dir_stack[0].display());
-        }
-    });
-    println!();
-    Ok(())
-}
-
-fn process_pushd(_cmdList: &Arc<CMD>) -> u32 {
-    if _cmdList.argc != 2 {
-        eprintln!("usage: pushd <dirName>");
-        return 1;
-    } else {
-        if let Some(dirName) = _cmdList.argv[1].as_ref() {
-            // get current directory
-            let currDir = match getcwd() {
-                Ok(path) => path,
-                Err(_) => {
-                    let err = std::ffi::CString::new("failed to get cwd").unwrap();
-                    unsafe {
-                        libc::perror(err.as_ptr());
-                    }
-                    return Errno::ENOENT as u32
-                }
-            };
-            // push curr dir to dir stack
-            DIR_STACK.with(|stack| {
-                // clone to avoid borrowing
-                stack.borrow_mut().push(currDir.clone());
-            });
-            // cd to dirName
-            if let Err(e) = cd_dir_name(&PathBuf::from(dirName)){
-                return e as u32;
-            };
-            if let Err(e) = print_dir_stack() {
-                return e as u32;
-            }
-        } else {
-            return Errno::EINVAL as u32;
-        }
-    }
-    0
-}
-
-fn process_popd(_cmdList: &Arc<CMD>) -> u32 {
-    if _cmdList.argc != 1 {
-        eprintln!("usage: popd");
-        return 1;
-    } else {
-         // pdir is None if stack is empty
-        let pdir = DIR_STACK.with(|stack| {
-            stack.borrow_mut().pop()
-        });
-        if pdir == None {
-            eprintln!("stack empty");
-            return 1;
-        }
-        // cd to directory
-        if let Some(dir) = pdir {
-            // println!("popped path: {}", dir.display());
-            if let Err(e) = cd_dir_name(&dir) {
-                return e as u32;
-            };
-        }
-        if let Err(e) = print_dir_stack() {
-            return e as u32;
-        }
-    }
-    0
+    error
 }
 
 fn exec_simple(_cmdList: &Arc<CMD>) -> Result<(), Errno> {
      // 1. Handle Locals
-     handle_locals(&_cmdList)?;
+     handle_locals(_cmdList)?;
      // 2. prepare program and args
-     let (program, args) = get_program_and_args(&_cmdList);
+     let (program, args) = get_program_and_args(_cmdList);
      // 3. Handle redirection (if necessary)
-     handle_redirection(&_cmdList)?;
+     handle_redirection(_cmdList)?;
      // 4. EXECVP CALL
-     match execvp(&program, &args) {
-         Ok(_) => (),
-         Err(_) => {
-            let err = std::ffi::CString::new("Execvp failed").unwrap();
-             unsafe { 
-                 libc::perror(err.as_ptr());
-                 libc::_exit(2);
-             }
-         }
-     }
-     Ok(())
+     let e = exec_program(&program, &args);
+     eprintln!("Execvp failed: {}", e.desc());
+     unsafe { libc::_exit(2); }
 }
 
-fn exec_stage(_cmdList: &Arc<CMD>) -> Result<(), Errno> {
+// Run one pipeline stage in its forked child and return the status the child
+// should exit with.  Builtins run right here, as in a bash subshell: they see
+// the stage's pipe fds but cannot change the parent shell's state.
+fn exec_stage(_cmdList: &Arc<CMD>) -> u32 {
     match _cmdList.node {
         x if x == Type::SUBCMD as u32 => {
-            let status = handle_subcmd(_cmdList);
-            if status != 0 {
-                return Err(Errno::last());
-            }
+            return handle_subcmd(_cmdList);
         },
         x if x == Type::SIMPLE as u32 => {
-            return exec_simple(&_cmdList);
+            let _cmdList = match expand_cmd(_cmdList) {
+                Ok(expanded) => expanded,
+                Err(_) => return EXIT_FAILURE as u32,
+            };
+            if _cmdList.argv.is_empty() {
+                return handle_null(&_cmdList);
+            }
+            if let Some(builtin) = builtin_for(&_cmdList) {
+                return process_built_in_simple(&_cmdList, builtin);
+            }
+            // exec_simple only returns if setup failed before execvp
+            if exec_simple(&_cmdList).is_err() {
+                return EXIT_FAILURE as u32;
+            }
         },
         _ => (),
     }
-    Ok(())
+    0
 }
 
 fn process_simple(_cmdList: &Arc<CMD>) -> u32 {
     match unsafe{fork()} {
         Ok(ForkResult::Parent { child, .. }) => {
-            let status = wait::waitpid(child, None).unwrap();
-            match status {
-                WaitStatus::Exited(_, code) => code as u32,
-                WaitStatus::Signaled(_, signal, _) => 128 + signal as u32,
-                // no errors!
-                _ => 0,
-            }
+            wait_foreground(_cmdList, child)
         },
         Ok(ForkResult::Child) => {
-            // return propagated error in setup phase first
-            if let Err(e) = exec_simple(_cmdList) {
-                return e as u32;
-            // if no propagated error, move on to parent handling
+            enter_child(Pid::from_raw(0), true);
+            // a setup failure (locals, redirection) has already been
+            // reported; the child must not fall back into the shell
+            if exec_simple(_cmdList).is_err() {
+                unsafe { libc::_exit(EXIT_FAILURE); }
             } else {
                 std::process::exit(0);
             }
@@ -332,34 +182,132 @@ fn process_simple(_cmdList: &Arc<CMD>) -> u32 {
     }
 }
 
-fn process_built_in_simple(_cmdList: &Arc<CMD>, cmd: &str) -> u32 {
-    if let Err(e) = handle_locals(&_cmdList) {
-        return e as u32;
-    };
-    if let Err(e) = handle_redirection(&_cmdList) {
-        return e as u32;
+// Wait for the single foreground process CHILD (in its own process group
+// under job control); a Ctrl-Z turns it into a stopped job.
+fn wait_foreground(_cmdList: &Arc<CMD>, child: Pid) -> u32 {
+    place_in_group(child, child);
+    give_terminal(child);
+    let status = wait_pid(child, wait_flags());
+    take_terminal();
+    match status {
+        Ok(WaitStatus::Stopped(..)) => {
+            let procs = vec![Entry { pid: child, status: status.unwrap() }];
+            stop_job(child, procs, command_text(_cmdList))
+        },
+        Ok(status) => {
+            report_foreground_signal(status);
+            status_code(status).unwrap_or(0)
+        },
+        Err(_) => EXIT_FAILURE as u32,
+    }
+}
+
+// Prefix assignments last only while a regular builtin runs, but those
+// before a special builtin (set, export, ...) stay in the shell, as POSIX
+// requires
+fn process_built_in_simple(_cmdList: &Arc<CMD>, builtin: Rc<dyn Builtin>) -> u32 {
+    if builtin.is_special() {
+        if let Err(e) = keep_locals(_cmdList) {
+            return e as u32;
+        }
+        return run_built_in(_cmdList, builtin);
+    }
+    push_locals();
+    let status = match handle_locals(_cmdList) {
+        Ok(()) => run_built_in(_cmdList, builtin),
+        Err(e) => e as u32,
     };
-    match cmd {
-        "cd" => return process_cd(_cmdList),
-        "pushd" => return process_pushd(_cmdList),
-        "popd" => return process_popd(_cmdList),
-        _ => 1
+    pop_locals();
+    status
+}
+
+// Assignments that stay in the shell as plain variables: those before a
+// special builtin, or making up a whole command
+fn keep_locals(_cmdList: &Arc<CMD>) -> Result<(), Errno> {
+    for n in 0.._cmdList.nLocal as usize {
+        if let (Some(name), Some(val)) = (_cmdList.locVar[n].as_ref(), _cmdList.locVal[n].as_ref()) {
+            assign_raw(name, val).map_err(|_| Errno::EPERM)?;
+        }
     }
+    Ok(())
+}
+
+fn run_built_in(_cmdList: &Arc<CMD>, builtin: Rc<dyn Builtin>) -> u32 {
+    // builtins run in the shell itself, so redirect around a saved copy of
+    // its descriptors rather than rewiring them for the rest of the session
+    let saved = match save_std_fds() {
+        Ok(saved) => saved,
+        Err(_) => {
+            let err = std::ffi::CString::new("cannot save file descriptors").unwrap();
+            unsafe { libc::perror(err.as_ptr()); }
+            return 1;
+        }
+    };
+    let status = if handle_redirection(_cmdList).is_err() {
+        1
+    } else {
+        let argv: Vec<String> = _cmdList.argv.iter().flatten().cloned().collect();
+        let mut io = BuiltinIo {
+            stdin: &mut FdStdin,
+            stdout: &mut std::io::stdout(),
+            stderr: &mut std::io::stderr(),
+        };
+        builtin.run(&argv, &mut io)
+    };
+    restore_std_fds(saved);
+    status
+}
+
+// the registered builtin _cmdList invokes, if argv[0] names one
+fn builtin_for(_cmdList: &Arc<CMD>) -> Option<Rc<dyn Builtin>> {
+    let cmd = _cmdList.argv.first()?.as_ref()?;
+    find_builtin(cmd)
 }
 
 pub fn handle_simple(_cmdList: &Arc<CMD>) -> u32 {
-    if let Some(cmd) =_cmdList.argv[0].as_ref() {
-        let command = cmd.as_str();
-        if BUILT_INS.contains(&command) {
-            return process_built_in_simple(_cmdList, &command);
+    if let Some(status) = assign_words(_cmdList) {
+        return status;
+    }
+    let _cmdList = match expand_cmd(_cmdList) {
+        Ok(expanded) => expanded,
+        Err(_) => return 1,
+    };
+    if _cmdList.argv.first().is_some_and(|cmd| cmd.is_some()) {
+        if let Some(builtin) = builtin_for(&_cmdList) {
+            process_built_in_simple(&_cmdList, builtin)
         } else {
-            return process_simple(_cmdList);
+            process_simple(&_cmdList)
         }
     } else {
-        1
+        // nothing left to run, as in "X=5", "> file" or "$(false)"
+        handle_null(&_cmdList)
     }
 }
 
+// A command with no words (after expansion): its assignments set shell
+// variables, and its redirections are made and undone, so "> file" creates
+// or truncates the file.  The status is that of the last command
+// substitution, or 1 if an assignment or redirection failed.
+fn handle_null(_cmdList: &Arc<CMD>) -> u32 {
+    if keep_locals(_cmdList).is_err() {
+        return 1;
+    }
+    let saved = match save_std_fds() {
+        Ok(saved) => saved,
+        Err(_) => {
+            let err = std::ffi::CString::new("cannot save file descriptors").unwrap();
+            unsafe { libc::perror(err.as_ptr()); }
+            return 1;
+        }
+    };
+    let redirected = handle_redirection(_cmdList);
+    restore_std_fds(saved);
+    if redirected.is_err() {
+        return 1;
+    }
+    substitution_status().unwrap_or(0)
+}
+
 fn handle_sep_end(_cmdList: &Arc<CMD>) -> u32 {
     let mut left_status = 0;
     let mut right_status = 0;
@@ -370,103 +318,17 @@ fn handle_sep_end(_cmdList: &Arc<CMD>) -> u32 {
         right_status = handle_any(right);
     }
     if left_status != 0 {
-        return left_status;
+        left_status
     } else if right_status != 0 {
-        return right_status;
+        right_status
     } else {
-        return left_status;
-    }
-}
-
-fn handle_heredoc(_cmdList: &Arc<CMD>) -> Result<i32, Errno> {
-    let temp = "/tmp/Bash_heredoc_XXXXXX";
-    let template = CString::new(temp).unwrap();
-    let mut template_bytes = template.into_bytes_with_nul();
-    let fd = unsafe { libc::mkstemp(template_bytes.as_mut_ptr() as *mut c_char) };
-    if fd < 0 {
-        return Err(Errno::last());
-    }
-    let mut flag = b"w+".to_vec();
-    let fp = unsafe { libc::fdopen(fd, flag.as_mut_ptr() as *const c_char) };
-    if fp.is_null() {
-        let err = CString::new("fdopen failed").unwrap();
-        unsafe {
-            libc::perror(err.as_ptr());
-            libc::_exit(EXIT_FAILURE);
-        }
+        left_status
     }
-    // write to temp file
-    if let Some(hd) = _cmdList.fromFile.as_ref() {
-        unsafe { 
-            if libc::fwrite(hd.as_ptr() as *mut c_void, 1, hd.len(), fp) != hd.len() {
-                return Err(Errno::last());
-            }
-        };
-    }
-    // flush the stream
-    if unsafe { libc::fflush(fp) } < 0 {
-        return Err(Errno::last());
-    }
-    // reset file offset to beginning
-    if unsafe { libc::lseek(fd, 0, libc::SEEK_SET) } < 0 {
-        return Err(Errno::last());
-    };
-    
-    if unsafe { unlink(template_bytes.as_ptr() as *const i8) } < 0 {
-        return Err(Errno::last());
-    };
-    Ok(fd)
 }
 
-pub fn handle_redirection(_cmdList: &Arc<CMD>) -> Result<(), Errno> {
-    if _cmdList.fromType != Type::NONE as u32 || _cmdList.toType != Type::NONE as u32 {
-    // HERE DOC
-        if _cmdList.fromType == Type::RED_IN_HERE as u32 {
-            let hfd = handle_heredoc(&_cmdList)?;
-            let _ = dup2_safe_simple(hfd, STDIN_FILENO);
-        } else {
-            // redirecting, replacing stdin or stdout with the appropriate file
-            match (_cmdList.fromFile.as_ref(), _cmdList.toFile.as_ref()) {
-                (Some(fromFile), _) => {
-                        let ifd = unsafe { libc::open(string2CStr(&fromFile).as_ptr(), O_RDONLY, 0o644)};
-                        if ifd < 0 {
-                            let err = std::ffi::CString::new("open error").unwrap();
-                            unsafe {
-                                libc::perror(err.as_ptr());
-                                libc::_exit(EXIT_FAILURE);
-                            }
-                        }
-                        let _ = dup2_safe_simple(ifd, STDIN_FILENO);
-                }
-                (_, Some(toFile)) => {
-                    let flags = if _cmdList.toType == Type::RED_OUT_APP as u32 {
-                        O_WRONLY | O_CREAT | O_APPEND
-                    } else {
-                        O_WRONLY | O_CREAT | O_TRUNC
-                    };                    
-                    let ofd = unsafe { libc::open(string2CStr(&toFile).as_ptr(), flags as i32, 0o644)};
-                    if ofd < 0 {
-                        let err = std::ffi::CString::new("open error").unwrap();
-                        unsafe {
-                            libc::perror(err.as_ptr());
-                            libc::_exit(EXIT_FAILURE);
-                        }
-                    }
-                    // overwrite stdout with ofd
-                    let _ = dup2_safe_simple(ofd, STDOUT_FILENO);
-                }
-                _ => {
-                    Some(());
-                }
-            }
-        }
-    }
-    Ok(())
-}
-
-// recursive
-fn create_pipe_cmd_array(_cmdList: &Arc<CMD>, cmdVec: &mut Vec<Arc<CMD>>) -> () {
-    if _cmdList.node == Type::SIMPLE as u32 {
+// recursive: only PIPE nodes are split, so a SUBCMD stays a single stage
+fn create_pipe_cmd_array(_cmdList: &Arc<CMD>, cmdVec: &mut Vec<Arc<CMD>>) {
+    if _cmdList.node != Type::PIPE as u32 {
         let cmdListClone = _cmdList.clone();
         cmdVec.push(cmdListClone);
     } else {
@@ -479,19 +341,7 @@ fn create_pipe_cmd_array(_cmdList: &Arc<CMD>, cmdVec: &mut Vec<Arc<CMD>>) -> ()
     }
 }
 
-fn dup2_safe_simple(source: i32, target: i32) -> Result<(), Errno> {
-    unsafe {
-        if libc::dup2(source, target) < 0 {
-            return Err(Errno::last());
-        }
-        if libc::close(source) < 0 {
-            return Err(Errno::last());
-        }
-    }
-    Ok(())
-}
-
-fn dup2_safe_pipe(source: i32, target: i32) -> () {
+fn dup2_safe_pipe(source: i32, target: i32) {
     unsafe {
         if libc::dup2(source, target) < 0 {
             libc::_exit(Errno::last() as i32);
@@ -506,7 +356,7 @@ fn dup2_safe_pipe(source: i32, target: i32) -> () {
 pub fn handle_pipe(_cmdList: &Arc<CMD>) -> i32 {
     let mut cmdVec: Vec<Arc<CMD>> = Vec::new();
     
-    create_pipe_cmd_array(&_cmdList, &mut cmdVec);
+    create_pipe_cmd_array(_cmdList, &mut cmdVec);
     
     let mut table: Vec<Entry> = vec![Entry {pid: Pid::from_raw(0), status: WaitStatus::StillAlive}; cmdVec.len()];
     let mut fdin = 0;
@@ -518,6 +368,11 @@ pub fn handle_pipe(_cmdList: &Arc<CMD>) -> i32 {
             match unsafe { fork() } {
                 Ok(ForkResult::Parent { child, .. }) => {
                     table[i].pid = child;
+                    // the whole pipeline is one job led by its first stage
+                    place_in_group(child, table[0].pid);
+                    if i == 0 {
+                        give_terminal(child);
+                    }
                     if i > 0 {
                         unsafe { if libc::close(fdin) < 0 {
                             libc::_exit(Errno::last() as i32);
@@ -529,6 +384,7 @@ pub fn handle_pipe(_cmdList: &Arc<CMD>) -> i32 {
                     }};
                 },
                 Ok(ForkResult::Child) => {
+                    enter_child(table[0].pid, true);
                     unsafe {
                         if libc::close(fdr) < 0 {
                             libc::_exit(Errno::last() as i32);
@@ -539,9 +395,7 @@ pub fn handle_pipe(_cmdList: &Arc<CMD>) -> i32 {
                         if fdw != 1 {
                             dup2_safe_pipe(fdw, STDOUT_FILENO);
                         }
-                        if let Err(e) = exec_stage(&cmdVec[i]) {
-                            libc::_exit(e as i32);
-                        }
+                        libc::_exit(exec_stage(&cmdVec[i]) as i32);
                     }
                 },
                 Err(_) => {
@@ -566,6 +420,10 @@ pub fn handle_pipe(_cmdList: &Arc<CMD>) -> i32 {
     match unsafe{fork()} {
         Ok(ForkResult::Parent { child, .. }) => {
             table[cmdVec.len() - 1].pid = child;
+            place_in_group(child, table[0].pid);
+            if i == 0 {
+                give_terminal(child);
+            }
             if i > 0 {
                 unsafe { if libc::close(fdin) < 0 {
                     libc::_exit(Errno::last() as i32);
@@ -573,12 +431,11 @@ pub fn handle_pipe(_cmdList: &Arc<CMD>) -> i32 {
             }
         }
         Ok(ForkResult::Child) => {
+            enter_child(table[0].pid, true);
             if fdin != 0 {
                 dup2_safe_pipe(fdin, STDIN_FILENO);
             }
-            if let Err(e) = exec_stage(&cmdVec[cmdVec.len() - 1]) {
-                unsafe {libc::_exit(e as i32); }
-            }
+            unsafe { libc::_exit(exec_stage(&cmdVec[cmdVec.len() - 1]) as i32); }
         }
         Err(_) => {
             unsafe {
@@ -589,52 +446,47 @@ pub fn handle_pipe(_cmdList: &Arc<CMD>) -> i32 {
         }
     }
     
-    // WAIT AND COLLECT
-    let mut i = 0;
-    while i < cmdVec.len() {
-        match wait::wait() {
+    // WAIT AND COLLECT: each stage by its own pid, so a background job that
+    // finishes meanwhile stays queued for reap_jobs() instead of being eaten
+    for i in 0..cmdVec.len() {
+        match wait_pid(table[i].pid, wait_flags()) {
+            // Ctrl-Z: the pipeline becomes a stopped job
+            Ok(status @ WaitStatus::Stopped(..)) => {
+                take_terminal();
+                table[i].status = status;
+                return stop_job(table[0].pid, table, command_text(_cmdList)) as i32;
+            },
             Ok(status) => {
-                match status {
-                    WaitStatus::Exited(pid, _) => {
-                        let mut j = 0;
-                        while j < cmdVec.len() && table[j].pid != pid {
-                            j += 1;
-                        }
-                        if j < cmdVec.len() {
-                            table[j].status = status;
-                            i += 1;
-                        }
-                    },
-                    _ => ()
-                }
-            }
-            Err(_) => ()
-        }
-    }
-    // RETURN RIGHTMOST STATUS
-    for i in (0..cmdVec.len()).rev() {
-        match table[i].status {
-            WaitStatus::Exited(_, status) if status != 0 => {
-                return status;
+                table[i].status = status;
             },
-            _ => ()
+            Err(_) => (),
         }
     }
-    0
+    report_foreground_signal(table[table.len() - 1].status);
+    take_terminal();
+    // every stage's status goes to PIPESTATUS; $? is the last stage's, or
+    // with pipefail the rightmost nonzero one
+    let statuses: Vec<u32> = table.iter().map(|entry| status_code(entry.status).unwrap_or(0)).collect();
+    let pipestatus = statuses.iter().enumerate().map(|(i, status)| (i as i64, status.to_string())).collect();
+    let _ = set_value("PIPESTATUS", Value::Indexed(pipestatus));
+    if option_enabled("pipefail") {
+        return statuses.iter().rev().find(|&&status| status != 0).copied().unwrap_or(0) as i32;
+    }
+    statuses[statuses.len() - 1] as i32
 }
 
 fn handle_cond(_cmdList: &Arc<CMD>) -> u32 {
     if let Some(left) = _cmdList.left.as_ref() {
-        let left_status = handle_any(&left);
+        let left_status = handle_any(left);
         match _cmdList.node {
             x if x == Type::SEP_AND as u32 => {
                 if left_status != 0 {
                     return left_status;
                 }
                 if let Some(right) = _cmdList.right.as_ref() {
-                    return handle_any(&right);
+                    handle_any(right)
                 } else {
-                    return 1;
+                    1
                 }
             },
             x if x == Type::SEP_OR as u32 => {
@@ -642,9 +494,9 @@ fn handle_cond(_cmdList: &Arc<CMD>) -> u32 {
                     return left_status;
                 }
                 if let Some(right) = _cmdList.right.as_ref() {
-                    return handle_any(&right);
+                    handle_any(right)
                 } else {
-                    return 1;
+                    1
                 }
             },
             _ => 1
@@ -656,12 +508,12 @@ fn handle_cond(_cmdList: &Arc<CMD>) -> u32 {
 
 
 // recursive
-fn create_bg_cmd_arrays(_cmdList: &Arc<CMD>, bgVec: &mut Vec<bool>, cmdVec: &mut Vec<Arc<CMD>>) -> () {
+fn create_bg_cmd_arrays(_cmdList: &Arc<CMD>, bgVec: &mut Vec<bool>, cmdVec: &mut Vec<Arc<CMD>>) {
     if let Some(left) = _cmdList.left.as_ref() {
         create_bg_cmd_arrays(left, bgVec, cmdVec);
     }
     // if sep_bg "&" AND the length of the vectors are more than 0
-    if _cmdList.node == Type::SEP_BG as u32 && bgVec.len() > 0 {
+    if _cmdList.node == Type::SEP_BG as u32 && !bgVec.is_empty() {
         // set the previous bg value to true
         let prev_i = bgVec.len() - 1;
         bgVec[prev_i] = true;
@@ -680,11 +532,22 @@ fn create_bg_cmd_arrays(_cmdList: &Arc<CMD>, bgVec: &mut Vec<bool>, cmdVec: &mut
 fn background(_cmdList: &Arc<CMD>) {
     match unsafe { fork() } {
         Ok(ForkResult::Parent { child, .. }) => {
-            eprintln!("Backgrounded: {}", child);
+            place_in_group(child, child);
+            let procs = vec![Entry { pid: child, status: WaitStatus::StillAlive }];
+            let id = add_job(child, procs, command_text(_cmdList), JobState::Running);
+            set_last_background(child.as_raw());
+            if interactive() {
+                eprintln!("[{}] {}", id, child);
+            }
         }
         Ok(ForkResult::Child) => {
-            handle_any(&_cmdList);
-            unsafe { libc::_exit(0)};
+            enter_child(Pid::from_raw(0), false);
+            // a lone external command is exec'd in place, so the job's
+            // status (or the signal that killed it) is the command's own
+            if _cmdList.node == Type::SIMPLE as u32 {
+                unsafe { libc::_exit(exec_stage(_cmdList) as i32); }
+            }
+            unsafe { libc::_exit(handle_any(_cmdList) as i32)};
         }
         Err(_) => {
         }
@@ -694,7 +557,7 @@ fn background(_cmdList: &Arc<CMD>) {
 fn handle_bg(_cmdList: &Arc<CMD>) -> u32 {
     let mut bgVec: Vec<bool> = Vec::new();
     let mut cmdVec: Vec<Arc<CMD>> = Vec::new();
-    create_bg_cmd_arrays(&_cmdList, &mut bgVec, &mut cmdVec);
+    create_bg_cmd_arrays(_cmdList, &mut bgVec, &mut cmdVec);
     // println!("Background Vector: {:?}", bgVec);
     // print!("Command Vector: [");
     // for cmd in cmdVec.iter() {
@@ -703,7 +566,7 @@ fn handle_bg(_cmdList: &Arc<CMD>) -> u32 {
     // println!("]");
 
     for i in 0..bgVec.len() {
-        if bgVec[i] == true {
+        if bgVec[i] {
             background(&cmdVec[i]);
         } else {
             handle_any(&cmdVec[i]);
@@ -715,19 +578,20 @@ fn handle_bg(_cmdList: &Arc<CMD>) -> u32 {
 fn handle_subcmd(_cmdList: &Arc<CMD>) -> u32 {
     match unsafe { fork() } {
         Ok(ForkResult::Parent { child, .. }) => {
-            let status = wait::waitpid(child, None).unwrap();
-            match status {
-                WaitStatus::Exited(_, code) => code as u32,
-                WaitStatus::Signaled(_, signal, _) => 128 + signal as u32,
-                // no errors!
-                _ => 0,
-            }
+            wait_foreground(_cmdList, child)
         }
         Ok(ForkResult::Child) => {
+            enter_child(Pid::from_raw(0), true);
+            let _cmdList = match expand_cmd(_cmdList) {
+                Ok(expanded) => expanded,
+                Err(_) => unsafe { libc::_exit(EXIT_FAILURE) },
+            };
             // 1. Handle Locals
             let _ = handle_locals(&_cmdList);
             // 3. Handle redirection (if necessary)
-            let _ = handle_redirection(&_cmdList);
+            if handle_redirection(&_cmdList).is_err() {
+                unsafe { libc::_exit(EXIT_FAILURE); }
+            }
             if let Some(left) = _cmdList.left.as_ref() {
                 unsafe { libc::_exit(handle_any(left) as i32) };
             } else {
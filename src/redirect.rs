@@ -0,0 +1,193 @@
+use std::ffi::{c_void, CString};
+use nix::errno::Errno;
+use std::os::raw::c_char;
+use crate::*;
+use crate::process::string2CStr;
+use libc::{unlink, O_APPEND, O_CREAT, O_RDONLY, O_TRUNC, O_WRONLY, STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO};
+
+// Apply every redirection on _cmdList, in a fixed order: stdin, stdout, then
+// stderr.  A failure is reported against the file that caused it and stops
+// the remaining redirections, so the command is never run half-redirected.
+pub fn handle_redirection(_cmdList: &Arc<CMD>) -> Result<(), Errno> {
+    redirect_stdin(_cmdList)?;
+    redirect_stdout(_cmdList)?;
+    redirect_stderr(_cmdList)?;
+    Ok(())
+}
+
+// <  and  <<
+fn redirect_stdin(_cmdList: &Arc<CMD>) -> Result<(), Errno> {
+    match _cmdList.fromType {
+        x if x == Type::RED_IN_HERE as u32 => {
+            let hfd = match handle_heredoc(_cmdList) {
+                Ok(fd) => fd,
+                Err(e) => {
+                    let err = string2CStr("here document");
+                    unsafe { libc::perror(err.as_ptr()); }
+                    return Err(e);
+                }
+            };
+            dup2_safe_simple(hfd, STDIN_FILENO)
+        },
+        x if x == Type::RED_IN as u32 => {
+            match _cmdList.fromFile.as_ref() {
+                Some(fromFile) => {
+                    let ifd = open_redirect_file(fromFile, O_RDONLY)?;
+                    dup2_safe_simple(ifd, STDIN_FILENO)
+                },
+                None => Ok(()),
+            }
+        },
+        _ => Ok(())
+    }
+}
+
+// >, >>  and  &>  (which also points stderr at the same open file)
+fn redirect_stdout(_cmdList: &Arc<CMD>) -> Result<(), Errno> {
+    if _cmdList.toType == Type::NONE as u32 {
+        return Ok(());
+    }
+    if let Some(toFile) = _cmdList.toFile.as_ref() {
+        let append = _cmdList.toType == Type::RED_OUT_APP as u32;
+        let ofd = open_redirect_file(toFile, output_flags(append))?;
+        dup2_safe_simple(ofd, STDOUT_FILENO)?;
+        if _cmdList.toType == Type::RED_OUT_ERR as u32 {
+            unsafe {
+                if libc::dup2(STDOUT_FILENO, STDERR_FILENO) < 0 {
+                    return Err(Errno::last());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// 2>  and  2>>  (&> leaves errFile NULL and is handled with stdout)
+fn redirect_stderr(_cmdList: &Arc<CMD>) -> Result<(), Errno> {
+    if _cmdList.errType != Type::RED_ERR as u32 && _cmdList.errType != Type::RED_ERR_APP as u32 {
+        return Ok(());
+    }
+    if let Some(errFile) = _cmdList.errFile.as_ref() {
+        let append = _cmdList.errType == Type::RED_ERR_APP as u32;
+        let efd = open_redirect_file(errFile, output_flags(append))?;
+        dup2_safe_simple(efd, STDERR_FILENO)?;
+    }
+    Ok(())
+}
+
+fn output_flags(append: bool) -> i32 {
+    if append {
+        O_WRONLY | O_CREAT | O_APPEND
+    } else {
+        O_WRONLY | O_CREAT | O_TRUNC
+    }
+}
+
+// open FILE for redirection, reporting "FILE: reason" on failure
+fn open_redirect_file(file: &str, flags: i32) -> Result<i32, Errno> {
+    let fd = unsafe { libc::open(string2CStr(file).as_ptr(), flags, 0o644) };
+    if fd < 0 {
+        let e = Errno::last();
+        let err = string2CStr(file);
+        unsafe { libc::perror(err.as_ptr()); }
+        return Err(e);
+    }
+    Ok(fd)
+}
+
+fn handle_heredoc(_cmdList: &Arc<CMD>) -> Result<i32, Errno> {
+    let temp = "/tmp/Bash_heredoc_XXXXXX";
+    let template = CString::new(temp).unwrap();
+    let mut template_bytes = template.into_bytes_with_nul();
+    let fd = unsafe { libc::mkstemp(template_bytes.as_mut_ptr() as *mut c_char) };
+    if fd < 0 {
+        return Err(Errno::last());
+    }
+    // unlink right away so the file disappears once fd is closed
+    if unsafe { unlink(template_bytes.as_ptr() as *const c_char) } < 0 {
+        let e = Errno::last();
+        unsafe { libc::close(fd); }
+        return Err(e);
+    };
+    // write to temp file
+    if let Some(hd) = _cmdList.fromFile.as_ref() {
+        let mut written = 0;
+        while written < hd.len() {
+            let n = unsafe {
+                libc::write(fd, hd[written..].as_ptr() as *const c_void, hd.len() - written)
+            };
+            if n < 0 {
+                if Errno::last() == Errno::EINTR {
+                    continue;
+                }
+                let e = Errno::last();
+                unsafe { libc::close(fd); }
+                return Err(e);
+            }
+            written += n as usize;
+        }
+    }
+    // reset file offset to beginning
+    if unsafe { libc::lseek(fd, 0, libc::SEEK_SET) } < 0 {
+        let e = Errno::last();
+        unsafe { libc::close(fd); }
+        return Err(e);
+    };
+    Ok(fd)
+}
+
+fn dup2_safe_simple(source: i32, target: i32) -> Result<(), Errno> {
+    if source == target {
+        return Ok(());
+    }
+    unsafe {
+        if libc::dup2(source, target) < 0 {
+            return Err(Errno::last());
+        }
+        if libc::close(source) < 0 {
+            return Err(Errno::last());
+        }
+    }
+    Ok(())
+}
+
+// Copies of the shell's own stdin, stdout and stderr, set aside while a
+// builtin runs in-process with its redirections applied.  A slot holds -1
+// when that descriptor was not open to begin with.
+pub struct SavedFds {
+    fds: [i32; 3],
+}
+
+pub fn save_std_fds() -> Result<SavedFds, Errno> {
+    let mut saved = SavedFds { fds: [-1; 3] };
+    for fd in STDIN_FILENO..=STDERR_FILENO {
+        // park the copy above the range a redirection could land on
+        let copy = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 10) };
+        if copy < 0 && Errno::last() != Errno::EBADF {
+            let e = Errno::last();
+            for &copy in saved.fds.iter().filter(|&&copy| copy >= 0) {
+                unsafe { libc::close(copy); }
+            }
+            return Err(e);
+        }
+        saved.fds[fd as usize] = copy;
+    }
+    Ok(saved)
+}
+
+// Put the shell's descriptors back after a builtin, flushing anything the
+// builtin left buffered for its (possibly redirected) stdout first.
+pub fn restore_std_fds(saved: SavedFds) {
+    let _ = std::io::Write::flush(&mut std::io::stdout());
+    for fd in STDIN_FILENO..=STDERR_FILENO {
+        let copy = saved.fds[fd as usize];
+        unsafe {
+            if copy < 0 {
+                libc::close(fd);
+            } else {
+                libc::dup2(copy, fd);
+                libc::close(copy);
+            }
+        }
+    }
+}
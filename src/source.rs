@@ -0,0 +1,81 @@
+use std::fs;
+use std::path::Path;
+use crate::*;
+use crate::builtins::BuiltinIo;
+use crate::dynamic::{line_number, set_line_number};
+use crate::parse::parse_line;
+use crate::process::r_process;
+use crate::vars::{get_var, positional, set_positional};
+use nix::errno::Errno;
+
+// The file a script name refers to: a name without a / is looked for in
+// $PATH first, then in the current directory
+fn find_script(name: &str) -> String {
+    if !name.contains('/') {
+        let path = get_var("PATH").unwrap_or_default();
+        for dir in path.split(':').filter(|dir| !dir.is_empty()) {
+            let candidate = format!("{}/{}", dir, name);
+            if Path::new(&candidate).is_file() {
+                return candidate;
+            }
+        }
+    }
+    name.to_string()
+}
+
+// Parse and run the commands in TEXT a line at a time (counting the lines
+// in $LINENO if COUNT_LINES) and return the status of the last one
+pub fn run_text(text: &str, count_lines: bool) -> u32 {
+    let mut lines = text.lines();
+    let mut next_line = move || {
+        let line = lines.next()?;
+        if count_lines {
+            set_line_number(line_number() + 1);
+        }
+        Some(line.to_string())
+    };
+    let mut status = 0;
+    while let Some(line) = next_line() {
+        if let Some(cmd) = parse_line(&line, &mut next_line) {
+            status = r_process(cmd);
+        }
+    }
+    status
+}
+
+// source FILE [arg ...] / . FILE [arg ...]: run the lines of FILE in the
+// current shell, with $LINENO counting them and the args (if any) as the
+// positional parameters meanwhile.  The status is that of the last command.
+pub fn process_source(argv: &[String], io: &mut BuiltinIo) -> u32 {
+    let name = match argv.get(1) {
+        Some(name) => name,
+        None => {
+            let _ = writeln!(io.stderr, "{}: filename argument required", argv[0]);
+            let _ = writeln!(io.stderr, "usage: {} filename [arguments]", argv[0]);
+            return 2;
+        }
+    };
+    let text = match fs::read_to_string(find_script(name)) {
+        Ok(text) => text,
+        Err(e) => {
+            let errno = Errno::from_i32(e.raw_os_error().unwrap_or(0));
+            let _ = writeln!(io.stderr, "{}: {}", name, errno.desc());
+            return 1;
+        }
+    };
+    let saved_params = if argv.len() > 2 {
+        let saved = positional();
+        set_positional(argv[2..].to_vec());
+        Some(saved)
+    } else {
+        None
+    };
+    let saved_line = line_number();
+    set_line_number(0);
+    let status = run_text(&text, true);
+    set_line_number(saved_line);
+    if let Some(params) = saved_params {
+        set_positional(params);
+    }
+    status
+}
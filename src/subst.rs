@@ -0,0 +1,65 @@
+use std::fs::File;
+use std::io::Read;
+use std::os::fd::FromRawFd;
+use crate::*;
+use crate::jobs::{enter_child, wait_pid};
+use crate::process::string2CStr;
+use crate::source::run_text;
+use libc::{EXIT_FAILURE, STDOUT_FILENO};
+use nix::sys::wait::WaitStatus;
+use nix::unistd::{fork, getpgrp, pipe, ForkResult};
+
+// Run TEXT as a command line in a forked child and return what it wrote to
+// stdout, minus trailing newlines, along with its exit status.  Err means
+// the child could not be started (and has been reported).
+pub fn command_output(text: &str) -> Result<(String, u32), ()> {
+    let (fdr, fdw) = match pipe() {
+        Ok(fds) => fds,
+        Err(_) => {
+            let err = string2CStr("pipe");
+            unsafe { libc::perror(err.as_ptr()); }
+            return Err(());
+        }
+    };
+    match unsafe { fork() } {
+        Ok(ForkResult::Parent { child, .. }) => {
+            unsafe { libc::close(fdw); }
+            // drain the pipe before waiting so a chatty child cannot block
+            let mut output = Vec::new();
+            let mut reader = unsafe { File::from_raw_fd(fdr) };
+            let _ = reader.read_to_end(&mut output);
+            drop(reader);
+            let status = match wait_pid(child, None) {
+                Ok(WaitStatus::Exited(_, code)) => code as u32,
+                Ok(WaitStatus::Signaled(_, sig, _)) => 128 + sig as u32,
+                _ => 1,
+            };
+            let output = String::from_utf8_lossy(&output);
+            Ok((output.trim_end_matches('\n').to_string(), status))
+        },
+        Ok(ForkResult::Child) => {
+            // stay in the shell's process group: the substitution is part of
+            // the command being expanded, not a job of its own
+            enter_child(getpgrp(), false);
+            unsafe {
+                libc::close(fdr);
+                if libc::dup2(fdw, STDOUT_FILENO) < 0 {
+                    libc::_exit(EXIT_FAILURE);
+                }
+                libc::close(fdw);
+            }
+            let status = run_text(text, false);
+            let _ = std::io::Write::flush(&mut std::io::stdout());
+            unsafe { libc::_exit(status as i32); }
+        },
+        Err(_) => {
+            unsafe {
+                libc::close(fdr);
+                libc::close(fdw);
+            }
+            let err = string2CStr("fork");
+            unsafe { libc::perror(err.as_ptr()); }
+            Err(())
+        },
+    }
+}
@@ -0,0 +1,752 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::CString;
+use crate::builtins::BuiltinIo;
+use crate::dynamic::{assign_dynamic, dynamic_value, record_start_time, DYNAMIC_NAMES};
+use crate::assign::assign_declared;
+use crate::expand::{split_subscript, subscript};
+
+// The shell's variables.  They start out as a copy of the environment the
+// shell was started with; afterwards the process environment is left alone
+// and children get one built from the exported variables instead.
+#[derive(Clone)]
+pub struct Var {
+    pub value: Value,
+    pub exported: bool,
+    pub readonly: bool,
+    // its value is computed when it is expanded (see dynamic.rs)
+    pub dynamic: bool,
+}
+
+// A variable's value: a string or an array of them.  Where a string is
+// wanted, an array stands for its element 0.
+#[derive(Clone)]
+pub enum Value {
+    Scalar(String),
+    // may have gaps, as after a[5]=x
+    Indexed(BTreeMap<i64, String>),
+    Assoc(BTreeMap<String, String>),
+}
+
+// An evaluated array subscript: an index into an indexed array (or into a
+// string, as its element 0), or a key into an associative one
+pub enum Subscript {
+    Index(i64),
+    Key(String),
+}
+
+impl Var {
+    fn new(value: Value) -> Var {
+        Var { value, exported: false, readonly: false, dynamic: false }
+    }
+}
+
+impl Value {
+    fn element(&self, subscript: &Subscript) -> Option<String> {
+        match (self, subscript) {
+            (Value::Scalar(value), Subscript::Index(0 | -1)) => Some(value.clone()),
+            (Value::Indexed(map), Subscript::Index(i)) => map.get(&resolve_index(map, *i)?).cloned(),
+            (Value::Assoc(map), Subscript::Key(key)) => map.get(key).cloned(),
+            _ => None,
+        }
+    }
+
+    // The value as a string: element 0 of an array
+    fn first(&self) -> Option<String> {
+        match self {
+            Value::Assoc(map) => map.get("0").cloned(),
+            _ => self.element(&Subscript::Index(0)),
+        }
+    }
+
+    fn set_first(&mut self, value: &str) {
+        match self {
+            Value::Scalar(old) => *old = value.to_string(),
+            Value::Indexed(map) => { map.insert(0, value.to_string()); },
+            Value::Assoc(map) => { map.insert(String::from("0"), value.to_string()); },
+        }
+    }
+
+    fn elements(&self) -> Vec<String> {
+        match self {
+            Value::Scalar(value) => vec![value.clone()],
+            Value::Indexed(map) => map.values().cloned().collect(),
+            Value::Assoc(map) => map.values().cloned().collect(),
+        }
+    }
+
+    fn keys(&self) -> Vec<String> {
+        match self {
+            Value::Scalar(_) => vec![String::from("0")],
+            Value::Indexed(map) => map.keys().map(|i| i.to_string()).collect(),
+            Value::Assoc(map) => map.keys().cloned().collect(),
+        }
+    }
+}
+
+// Index I of MAP, where a negative index counts back from the end
+fn resolve_index(map: &BTreeMap<i64, String>, i: i64) -> Option<i64> {
+    if i >= 0 {
+        return Some(i);
+    }
+    let end = map.keys().next_back().map_or(0, |last| last + 1);
+    if end + i < 0 { None } else { Some(end + i) }
+}
+
+// The variables a set of local assignments overwrote, with their old values
+// (None if they were unset)
+type Frame = Vec<(String, Option<Var>)>;
+
+thread_local! {
+    static VARS: RefCell<HashMap<String, Var>> = RefCell::new(import_environment());
+    // one Frame for each active set of local (per-command) assignments
+    static FRAMES: RefCell<Vec<Frame>> = const { RefCell::new(Vec::new()) };
+    // $?
+    static LAST_STATUS: Cell<u32> = const { Cell::new(0) };
+    // $0, and the positional parameters $1, $2, ...
+    static SHELL_NAME: RefCell<String> = const { RefCell::new(String::new()) };
+    static POSITIONAL: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    // commands are being read from a script rather than standard input
+    static SCRIPT: Cell<bool> = const { Cell::new(false) };
+    // $$, recorded at startup so that subshells report the same pid
+    static SHELL_PID: Cell<i32> = const { Cell::new(0) };
+    // $!
+    static LAST_BACKGROUND: Cell<Option<i32>> = const { Cell::new(None) };
+}
+
+fn import_environment() -> HashMap<String, Var> {
+    let mut vars: HashMap<String, Var> = std::env::vars_os()
+        .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+        .map(|(name, value)| (name, Var { exported: true, ..Var::new(Value::Scalar(value)) }))
+        .collect();
+    for name in DYNAMIC_NAMES {
+        vars.insert(name.to_string(), Var { dynamic: true, ..Var::new(Value::Scalar(String::new())) });
+    }
+    vars
+}
+
+pub fn last_status() -> u32 {
+    LAST_STATUS.with(|s| s.get())
+}
+
+pub fn set_last_status(status: u32) {
+    LAST_STATUS.with(|s| s.set(status));
+}
+
+// Set $0 and the positional parameters from the shell's own arguments: the
+// first is the shell's name, unless it was given a script to run, in which
+// case the script's name and its arguments are used
+pub fn set_arguments(mut args: Vec<String>) {
+    let script = args.len() > 1;
+    if script {
+        args.remove(0);
+    }
+    let mut args = args.into_iter();
+    SHELL_NAME.with(|name| *name.borrow_mut() = args.next().unwrap_or_default());
+    SCRIPT.with(|s| s.set(script));
+    set_positional(args.collect());
+}
+
+pub fn shell_name() -> String {
+    SHELL_NAME.with(|name| name.borrow().clone())
+}
+
+pub fn reading_script() -> bool {
+    SCRIPT.with(|s| s.get())
+}
+
+pub fn positional() -> Vec<String> {
+    POSITIONAL.with(|params| params.borrow().clone())
+}
+
+pub fn set_positional(params: Vec<String>) {
+    POSITIONAL.with(|p| *p.borrow_mut() = params);
+}
+
+// Note the shell's pid and start time, for $$ and $SECONDS
+pub fn record_startup() {
+    SHELL_PID.with(|pid| pid.set(std::process::id() as i32));
+    record_start_time();
+}
+
+pub fn shell_pid() -> i32 {
+    SHELL_PID.with(|pid| pid.get())
+}
+
+pub fn last_background() -> Option<i32> {
+    LAST_BACKGROUND.with(|pid| pid.get())
+}
+
+pub fn set_last_background(pid: i32) {
+    LAST_BACKGROUND.with(|p| p.set(Some(pid)));
+}
+
+pub fn get_var(name: &str) -> Option<String> {
+    VARS.with(|vars| {
+        let vars = vars.borrow();
+        let var = vars.get(name)?;
+        if var.dynamic { Some(dynamic_value(name)) } else { var.value.first() }
+    })
+}
+
+// Element SUBSCRIPT of array NAME
+pub fn get_element(name: &str, subscript: &Subscript) -> Option<String> {
+    VARS.with(|vars| vars.borrow().get(name)?.value.element(subscript))
+}
+
+// All the elements of NAME, in order (a string is a single element)
+pub fn get_elements(name: &str) -> Option<Vec<String>> {
+    VARS.with(|vars| Some(vars.borrow().get(name)?.value.elements()))
+}
+
+// The indexes or keys of NAME's elements, in the same order
+pub fn get_keys(name: &str) -> Option<Vec<String>> {
+    VARS.with(|vars| Some(vars.borrow().get(name)?.value.keys()))
+}
+
+pub fn is_assoc(name: &str) -> bool {
+    VARS.with(|vars| vars.borrow().get(name).is_some_and(|var| matches!(var.value, Value::Assoc(_))))
+}
+
+pub fn is_name(name: &str) -> bool {
+    name.starts_with(|c: char| c == '_' || c.is_ascii_alphabetic())
+        && name.chars().all(|c| c == '_' || c.is_ascii_alphanumeric())
+}
+
+// Set NAME to VALUE, keeping its attributes; an error (already reported) if
+// it is readonly
+pub fn set_var(name: &str, value: &str) -> Result<(), ()> {
+    VARS.with(|vars| {
+        let mut vars = vars.borrow_mut();
+        match vars.get_mut(name) {
+            Some(var) if var.readonly => {
+                eprintln!("{}: readonly variable", name);
+                return Err(());
+            },
+            Some(var) => {
+                if var.dynamic {
+                    assign_dynamic(name, value);
+                }
+                var.value.set_first(value);
+            },
+            None => {
+                vars.insert(name.to_string(), Var::new(Value::Scalar(value.to_string())));
+            },
+        }
+        Ok(())
+    })
+}
+
+// Set element SUBSCRIPT of array NAME, making NAME an array if it is a
+// string (whose value becomes element 0) or unset
+pub fn set_element(name: &str, subscript: Subscript, value: &str) -> Result<(), ()> {
+    VARS.with(|vars| {
+        let mut vars = vars.borrow_mut();
+        let var = vars.entry(name.to_string()).or_insert(Var::new(Value::Indexed(BTreeMap::new())));
+        if var.readonly {
+            eprintln!("{}: readonly variable", name);
+            return Err(());
+        }
+        if let Value::Scalar(old) = &var.value {
+            var.value = Value::Indexed(BTreeMap::from([(0, old.clone())]));
+        }
+        match (&mut var.value, subscript) {
+            (Value::Indexed(map), Subscript::Index(i)) => match resolve_index(map, i) {
+                Some(i) => { map.insert(i, value.to_string()); },
+                None => {
+                    eprintln!("{}[{}]: bad array subscript", name, i);
+                    return Err(());
+                }
+            },
+            (Value::Assoc(map), Subscript::Key(key)) => { map.insert(key, value.to_string()); },
+            _ => return Err(()),
+        }
+        Ok(())
+    })
+}
+
+// Replace the whole value of NAME, as for a=(x y z), keeping its attributes
+pub fn set_value(name: &str, value: Value) -> Result<(), ()> {
+    VARS.with(|vars| {
+        let mut vars = vars.borrow_mut();
+        match vars.get_mut(name) {
+            Some(var) if var.readonly => {
+                eprintln!("{}: readonly variable", name);
+                return Err(());
+            },
+            Some(var) => var.value = value,
+            None => {
+                vars.insert(name.to_string(), Var::new(value));
+            },
+        }
+        Ok(())
+    })
+}
+
+// Make NAME an indexed or (if ASSOC) associative array, creating it if
+// needed; a string value becomes element 0.  Err holds the complaint.
+fn make_array(name: &str, assoc: bool) -> Result<(), String> {
+    VARS.with(|vars| {
+        let mut vars = vars.borrow_mut();
+        let var = vars.entry(name.to_string()).or_insert(Var::new(Value::Scalar(String::new())));
+        var.value = match (&var.value, assoc) {
+            (Value::Indexed(_), true) => return Err(format!("{}: cannot convert indexed to associative array", name)),
+            (Value::Assoc(_), false) => return Err(format!("{}: cannot convert associative to indexed array", name)),
+            (Value::Scalar(old), true) if old.is_empty() => Value::Assoc(BTreeMap::new()),
+            (Value::Scalar(old), true) => Value::Assoc(BTreeMap::from([(String::from("0"), old.clone())])),
+            (Value::Scalar(old), false) if old.is_empty() => Value::Indexed(BTreeMap::new()),
+            (Value::Scalar(old), false) => Value::Indexed(BTreeMap::from([(0, old.clone())])),
+            (value, _) => value.clone(),
+        };
+        Ok(())
+    })
+}
+
+// Give NAME the export or readonly attribute, creating it (empty) if needed
+fn set_attribute(name: &str, exported: Option<bool>, readonly: bool) {
+    VARS.with(|vars| {
+        let mut vars = vars.borrow_mut();
+        let var = vars.entry(name.to_string())
+            .or_insert(Var::new(Value::Scalar(String::new())));
+        if let Some(exported) = exported {
+            var.exported = exported;
+        }
+        var.readonly |= readonly;
+    });
+}
+
+pub fn export_var(name: &str) {
+    set_attribute(name, Some(true), false);
+}
+
+pub fn unset_var(name: &str) -> Result<(), ()> {
+    VARS.with(|vars| {
+        let mut vars = vars.borrow_mut();
+        if vars.get(name).is_some_and(|var| var.readonly) {
+            eprintln!("unset: {}: cannot unset: readonly variable", name);
+            return Err(());
+        }
+        vars.remove(name);
+        Ok(())
+    })
+}
+
+// Unset element SUBSCRIPT of array NAME
+pub fn unset_element(name: &str, subscript: &Subscript) -> Result<(), ()> {
+    VARS.with(|vars| {
+        let mut vars = vars.borrow_mut();
+        let var = match vars.get_mut(name) {
+            Some(var) => var,
+            None => return Ok(()),
+        };
+        if var.readonly {
+            eprintln!("unset: {}: cannot unset: readonly variable", name);
+            return Err(());
+        }
+        let whole = match (&mut var.value, subscript) {
+            (Value::Indexed(map), Subscript::Index(i)) => {
+                if let Some(i) = resolve_index(map, *i) {
+                    map.remove(&i);
+                }
+                false
+            },
+            (Value::Assoc(map), Subscript::Key(key)) => {
+                map.remove(key);
+                false
+            },
+            // element 0 of a string is the whole variable
+            (Value::Scalar(_), Subscript::Index(0 | -1)) => true,
+            _ => false,
+        };
+        if whole {
+            vars.remove(name);
+        }
+        Ok(())
+    })
+}
+
+// Start a set of local assignments, undone by pop_locals()
+pub fn push_locals() {
+    FRAMES.with(|frames| frames.borrow_mut().push(Vec::new()));
+}
+
+// Set NAME to VALUE (exported) until the matching pop_locals()
+pub fn set_local(name: &str, value: &str) -> Result<(), ()> {
+    let old = VARS.with(|vars| vars.borrow().get(name).cloned());
+    set_var(name, value)?;
+    export_var(name);
+    FRAMES.with(|frames| {
+        if let Some(frame) = frames.borrow_mut().last_mut() {
+            frame.push((name.to_string(), old));
+        }
+    });
+    Ok(())
+}
+
+// Put back what the innermost set of local assignments overwrote
+pub fn pop_locals() {
+    let frame = FRAMES.with(|frames| frames.borrow_mut().pop()).unwrap_or_default();
+    VARS.with(|vars| {
+        let mut vars = vars.borrow_mut();
+        // latest first, so a name assigned twice ends up with its original value
+        for (name, old) in frame.into_iter().rev() {
+            match old {
+                Some(var) => vars.insert(name, var),
+                None => vars.remove(&name),
+            };
+        }
+    });
+}
+
+// "NAME=value" for each exported variable, for a child's environment
+pub fn environment() -> Vec<CString> {
+    VARS.with(|vars| {
+        vars.borrow().iter()
+            .filter_map(|(name, var)| match &var.value {
+                // arrays cannot be exported
+                Value::Scalar(value) if var.exported => CString::new(format!("{}={}", name, value)).ok(),
+                _ => None,
+            })
+            .collect()
+    })
+}
+
+// VALUE quoted for reuse as shell input
+fn quote(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        if "\"\\$`".contains(c) {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+// Whether VAR has attribute FLAG, one of the letters of declare's options
+fn has_attribute(var: &Var, flag: char) -> bool {
+    match flag {
+        'a' => matches!(var.value, Value::Indexed(_)),
+        'A' => matches!(var.value, Value::Assoc(_)),
+        'r' => var.readonly,
+        'x' => var.exported,
+        _ => true,
+    }
+}
+
+// The declare command that recreates variable NAME
+fn declaration(name: &str, var: &Var) -> String {
+    let mut flags: String = "aArx".chars().filter(|&flag| has_attribute(var, flag)).collect();
+    if flags.is_empty() {
+        flags.push('-');
+    }
+    let value = match &var.value {
+        Value::Scalar(value) => quote(value),
+        Value::Indexed(map) => {
+            let items: Vec<String> = map.iter().map(|(i, v)| format!("[{}]={}", i, quote(v))).collect();
+            format!("({})", items.join(" "))
+        },
+        Value::Assoc(map) => {
+            let items: Vec<String> = map.iter().map(|(k, v)| format!("[{}]={} ", k, quote(v))).collect();
+            format!("({})", items.concat())
+        },
+    };
+    format!("declare -{} {}={}", flags, name, value)
+}
+
+// Print the variables with every attribute in WANT (letters from "aArx";
+// all variables if it is empty), sorted by name
+fn print_vars(want: &str, io: &mut BuiltinIo) {
+    let mut listed: Vec<(String, Var)> = VARS.with(|vars| {
+        vars.borrow().iter()
+            .filter(|(_, var)| want.chars().all(|flag| has_attribute(var, flag)))
+            .map(|(name, var)| (name.clone(), var.clone()))
+            .collect()
+    });
+    listed.sort_by(|a, b| a.0.cmp(&b.0));
+    for (name, var) in listed {
+        let _ = writeln!(io.stdout, "{}", declaration(&name, &var));
+    }
+}
+
+// The options given to declare, export or readonly
+struct Flags {
+    // attributes to add: any of a, A, r and x
+    add: String,
+    // +x or export -n
+    unexport: bool,
+    // -p: print instead of declaring
+    print: bool,
+}
+
+// Split the leading -x, +x, -r, -p style options (letters from ALLOWED) off
+// ARGV; Err holds a bad one
+fn parse_flags<'a>(argv: &'a [String], allowed: &str) -> Result<(Flags, Vec<&'a str>), String> {
+    let mut flags = Flags { add: String::new(), unexport: false, print: false };
+    let mut i = 1;
+    while i < argv.len() {
+        let arg = argv[i].as_str();
+        if arg == "--" {
+            i += 1;
+            break;
+        }
+        let (on, letters) = match (arg.strip_prefix('-'), arg.strip_prefix('+')) {
+            (Some(letters), _) if !letters.is_empty() => (true, letters),
+            (_, Some(letters)) if !letters.is_empty() => (false, letters),
+            _ => break,
+        };
+        for c in letters.chars() {
+            if !allowed.contains(c) {
+                return Err(format!("{}{}", if on { '-' } else { '+' }, c));
+            }
+            match (c, on) {
+                ('p', _) => flags.print = true,
+                ('n', _) | ('x', false) => flags.unexport = true,
+                (c, true) => flags.add.push(c),
+                (_, false) => (),
+            }
+        }
+        i += 1;
+    }
+    Ok((flags, argv[i..].iter().map(|a| a.as_str()).collect()))
+}
+
+// declare, export and readonly: give each NAME or NAME=value in ARGS the
+// attributes in FLAGS; with no ARGS, list the variables that have them
+fn declare_vars(command: &str, flags: Flags, args: &[&str], io: &mut BuiltinIo) -> u32 {
+    if args.is_empty() {
+        print_vars(&flags.add, io);
+        return 0;
+    }
+    let mut status = 0;
+    for arg in args {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (*arg, None),
+        };
+        // NAME[SUB]=value sets a single element
+        let base = split_subscript(name).map_or(name, |(base, _)| base);
+        if !is_name(base) {
+            let _ = writeln!(io.stderr, "{}: `{}': not a valid identifier", command, arg);
+            status = 1;
+            continue;
+        }
+        if flags.print {
+            match VARS.with(|vars| vars.borrow().get(name).cloned()) {
+                Some(var) => {
+                    let _ = writeln!(io.stdout, "{}", declaration(name, &var));
+                },
+                None => {
+                    let _ = writeln!(io.stderr, "{}: {}: not found", command, name);
+                    status = 1;
+                }
+            }
+            continue;
+        }
+        if flags.add.contains(['a', 'A']) {
+            if let Err(message) = make_array(base, flags.add.contains('A')) {
+                let _ = writeln!(io.stderr, "{}: {}", command, message);
+                status = 1;
+                continue;
+            }
+        }
+        if let Some(value) = value {
+            if assign_declared(name, value).is_err() {
+                status = 1;
+                continue;
+            }
+        }
+        let exported = if flags.add.contains('x') { Some(true) } else if flags.unexport { Some(false) } else { None };
+        set_attribute(base, exported, flags.add.contains('r'));
+    }
+    status
+}
+
+// declare [-aArx] [+x] [-p] [name[=value] ...]
+pub fn process_declare(argv: &[String], io: &mut BuiltinIo) -> u32 {
+    match parse_flags(argv, "aArxp") {
+        Ok((flags, args)) => declare_vars("declare", flags, &args, io),
+        Err(flag) => {
+            let _ = writeln!(io.stderr, "declare: {}: invalid option", flag);
+            let _ = writeln!(io.stderr, "usage: declare [-aArx] [-p] [name[=value] ...]");
+            2
+        }
+    }
+}
+
+// export [-n] [-p] [name[=value] ...]; -n takes the export attribute away
+pub fn process_export(argv: &[String], io: &mut BuiltinIo) -> u32 {
+    match parse_flags(argv, "np") {
+        Ok((mut flags, args)) => {
+            if !flags.unexport {
+                flags.add.push('x');
+            }
+            declare_vars("export", flags, &args, io)
+        },
+        Err(flag) => {
+            let _ = writeln!(io.stderr, "export: {}: invalid option", flag);
+            let _ = writeln!(io.stderr, "usage: export [-n] [-p] [name[=value] ...]");
+            2
+        }
+    }
+}
+
+// readonly [-p] [name[=value] ...]
+pub fn process_readonly(argv: &[String], io: &mut BuiltinIo) -> u32 {
+    match parse_flags(argv, "p") {
+        Ok((mut flags, args)) => {
+            flags.add.push('r');
+            declare_vars("readonly", flags, &args, io)
+        },
+        Err(flag) => {
+            let _ = writeln!(io.stderr, "readonly: {}: invalid option", flag);
+            let _ = writeln!(io.stderr, "usage: readonly [-p] [name[=value] ...]");
+            2
+        }
+    }
+}
+
+// unset [-v] name ...
+pub fn process_unset(argv: &[String], io: &mut BuiltinIo) -> u32 {
+    let args = match parse_flags(argv, "v") {
+        Ok((_, args)) => args,
+        Err(flag) => {
+            let _ = writeln!(io.stderr, "unset: {}: invalid option", flag);
+            let _ = writeln!(io.stderr, "usage: unset [-v] name ...");
+            return 2;
+        }
+    };
+    let mut status = 0;
+    for name in args {
+        let result = match split_subscript(name) {
+            // NAME[@] is the whole array
+            Some((base, "@" | "*")) => unset_var(base),
+            Some((base, sub)) => subscript(base, sub).and_then(|sub| unset_element(base, &sub)),
+            None if is_name(name) => unset_var(name),
+            None => {
+                let _ = writeln!(io.stderr, "unset: `{}': not a valid identifier", name);
+                Err(())
+            }
+        };
+        if result.is_err() {
+            status = 1;
+        }
+    }
+    status
+}
+
+// shift [n]: drop the first N (default 1) positional parameters
+pub fn process_shift(argv: &[String], io: &mut BuiltinIo) -> u32 {
+    let count = match argv.get(1).map(|n| n.parse::<usize>()) {
+        None => 1,
+        Some(Ok(count)) => count,
+        Some(Err(_)) => {
+            let _ = writeln!(io.stderr, "shift: {}: numeric argument required", argv[1]);
+            return 1;
+        }
+    };
+    let mut params = positional();
+    if count > params.len() {
+        let _ = writeln!(io.stderr, "shift: {}: shift count out of range", count);
+        return 1;
+    }
+    params.drain(..count);
+    set_positional(params);
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Run a builtin like process_export on ARGS; its status and stderr
+    fn run(builtin: fn(&[String], &mut BuiltinIo) -> u32, args: &[&str]) -> (u32, String) {
+        let argv: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+        let mut io = BuiltinIo { stdin: &mut std::io::empty(), stdout: &mut stdout, stderr: &mut stderr };
+        let status = builtin(&argv, &mut io);
+        (status, String::from_utf8(stderr).unwrap())
+    }
+
+    fn exported(name: &str) -> Option<String> {
+        let prefix = format!("{}=", name);
+        environment().into_iter()
+            .map(|entry| entry.into_string().unwrap())
+            .find_map(|entry| entry.strip_prefix(&prefix).map(String::from))
+    }
+
+    #[test]
+    fn readonly_variables_cannot_change() {
+        assert_eq!(run(process_readonly, &["readonly", "r=1"]).0, 0);
+        assert!(set_var("r", "2").is_err());
+        assert!(set_element("r", Subscript::Index(1), "2").is_err());
+        assert!(set_value("r", Value::Scalar(String::from("2"))).is_err());
+        assert!(unset_var("r").is_err());
+        assert_eq!(run(process_unset, &["unset", "r"]).0, 1);
+        assert_eq!(run(process_declare, &["declare", "r=2"]).0, 1);
+        assert_eq!(get_var("r").as_deref(), Some("1"));
+    }
+
+    #[test]
+    fn environment_has_only_exported_scalars() {
+        set_var("plain", "p").unwrap();
+        assert_eq!(run(process_export, &["export", "shown=s", "arr"]).0, 0);
+        set_element("arr", Subscript::Index(0), "a").unwrap();
+        assert_eq!(exported("shown").as_deref(), Some("s"));
+        assert_eq!(exported("plain"), None);
+        assert_eq!(exported("arr"), None);
+        // a later assignment is exported too, until export -n
+        set_var("shown", "t").unwrap();
+        assert_eq!(exported("shown").as_deref(), Some("t"));
+        assert_eq!(run(process_export, &["export", "-n", "shown"]).0, 0);
+        assert_eq!(exported("shown"), None);
+        assert_eq!(get_var("shown").as_deref(), Some("t"));
+    }
+
+    #[test]
+    fn unset_removes_the_variable() {
+        assert_eq!(run(process_export, &["export", "gone=g"]).0, 0);
+        assert_eq!(run(process_unset, &["unset", "gone"]).0, 0);
+        assert_eq!(get_var("gone"), None);
+        assert_eq!(exported("gone"), None);
+        // unsetting a name that is not set is fine
+        assert_eq!(run(process_unset, &["unset", "gone"]).0, 0);
+    }
+
+    #[test]
+    fn local_frames_restore_old_values() {
+        set_var("kept", "old").unwrap();
+        let _ = unset_var("fresh");
+        push_locals();
+        set_local("kept", "new").unwrap();
+        set_local("fresh", "f").unwrap();
+        assert_eq!(exported("kept").as_deref(), Some("new"));
+        push_locals();
+        set_local("kept", "newer").unwrap();
+        set_local("kept", "newest").unwrap();
+        pop_locals();
+        assert_eq!(get_var("kept").as_deref(), Some("new"));
+        pop_locals();
+        assert_eq!(get_var("kept").as_deref(), Some("old"));
+        assert_eq!(exported("kept"), None);
+        assert_eq!(get_var("fresh"), None);
+    }
+
+    #[test]
+    fn locals_cannot_override_readonly() {
+        assert_eq!(run(process_readonly, &["readonly", "fixed=1"]).0, 0);
+        push_locals();
+        assert!(set_local("fixed", "2").is_err());
+        pop_locals();
+        assert_eq!(get_var("fixed").as_deref(), Some("1"));
+    }
+
+    #[test]
+    fn bad_names_are_rejected() {
+        let (status, stderr) = run(process_declare, &["declare", "1x=y", "ok=z"]);
+        assert_eq!(status, 1);
+        assert!(stderr.contains("`1x=y': not a valid identifier"));
+        assert_eq!(get_var("ok").as_deref(), Some("z"));
+    }
+}
@@ -0,0 +1,273 @@
+// A word after expansion, kept as runs of text that remember whether they
+// were quoted and whether they came from an expansion.  Only unquoted
+// expansion results are split on $IFS, quoted text is never treated as a
+// pattern, and quote removal is joining the runs back together.
+#[derive(Clone, Default)]
+pub struct Word {
+    pub segments: Vec<Segment>,
+}
+
+#[derive(Clone)]
+pub struct Segment {
+    pub text: String,
+    pub quoted: bool,
+    pub expanded: bool,
+    // a field break, as between the parameters of "$@"
+    pub boundary: bool,
+}
+
+impl Word {
+    // A word holding the result of an expansion
+    pub fn from_expansion(text: &str, quoted: bool) -> Word {
+        let mut word = Word::default();
+        word.push_expansion(text, quoted);
+        word
+    }
+
+    // Add literal TEXT to the end.  Empty quoted text is still recorded,
+    // since "" makes an argument even though it contributes no characters.
+    pub fn push(&mut self, text: &str, quoted: bool) {
+        self.push_segment(text, quoted, false);
+    }
+
+    // Add the result of an expansion; unquoted, it is subject to splitting
+    pub fn push_expansion(&mut self, text: &str, quoted: bool) {
+        self.push_segment(text, quoted, !quoted);
+    }
+
+    fn push_segment(&mut self, text: &str, quoted: bool, expanded: bool) {
+        match self.segments.last_mut() {
+            Some(last) if last.quoted == quoted && last.expanded == expanded && !last.boundary => {
+                last.text.push_str(text)
+            },
+            _ if text.is_empty() && !quoted => (),
+            _ => self.segments.push(Segment {
+                text: text.to_string(), quoted, expanded, boundary: false,
+            }),
+        }
+    }
+
+    // End the current field here even if it is quoted, as between the
+    // parameters of "$@".  Where the word is not split, the break is a space.
+    pub fn push_break(&mut self) {
+        self.segments.push(Segment { text: String::from(" "), quoted: true, expanded: false, boundary: true });
+    }
+
+    pub fn append(&mut self, other: Word) {
+        for segment in other.segments {
+            if segment.boundary {
+                self.push_break();
+            } else {
+                self.push_segment(&segment.text, segment.quoted, segment.expanded);
+            }
+        }
+    }
+
+    // The word as the result of an expansion, as for the default in
+    // ${NAME:-a b}: its unquoted text becomes subject to splitting
+    pub fn into_expansion(mut self) -> Word {
+        for segment in self.segments.iter_mut() {
+            segment.expanded = !segment.quoted;
+        }
+        self
+    }
+
+    // Split the word into fields at the IFS characters in unquoted expansion
+    // results.  Runs of IFS whitespace count as one separator and are
+    // dropped at the ends; each other IFS character ends a field, possibly an
+    // empty one.  A word left with nothing at all yields no fields.
+    pub fn split(self, ifs: &str) -> Vec<Word> {
+        let mut fields = Vec::new();
+        let mut field = Word::default();
+        // field has started (holds text or quotes)
+        let mut started = false;
+        // the last field was ended by whitespace and nothing has followed
+        let mut after_space = false;
+        for segment in self.segments {
+            if segment.boundary {
+                if started {
+                    fields.push(std::mem::take(&mut field));
+                }
+                started = false;
+                after_space = false;
+                continue;
+            }
+            if !segment.expanded {
+                started |= segment.quoted || !segment.text.is_empty();
+                after_space &= segment.text.is_empty();
+                field.push(&segment.text, segment.quoted);
+                continue;
+            }
+            for c in segment.text.chars() {
+                if !ifs.contains(c) {
+                    field.push_expansion(&c.to_string(), false);
+                    started = true;
+                    after_space = false;
+                } else if " \t\n".contains(c) {
+                    if started {
+                        fields.push(std::mem::take(&mut field));
+                        started = false;
+                        after_space = true;
+                    }
+                } else {
+                    if started || !after_space {
+                        fields.push(std::mem::take(&mut field));
+                    }
+                    started = false;
+                    after_space = false;
+                }
+            }
+        }
+        if started {
+            fields.push(field);
+        }
+        fields
+    }
+
+    // The word with its quotes removed
+    pub fn text(&self) -> String {
+        self.segments.iter().map(|s| s.text.as_str()).collect()
+    }
+
+    // The word as a shell pattern: quoted characters match only themselves
+    pub fn pattern(&self) -> String {
+        let mut pattern = String::new();
+        for segment in &self.segments {
+            for c in segment.text.chars() {
+                if segment.quoted && "*?[]\\".contains(c) {
+                    pattern.push('\\');
+                }
+                pattern.push(c);
+            }
+        }
+        pattern
+    }
+}
+
+// The value of $'TEXT': TEXT with its ANSI-C backslash escapes decoded
+pub fn ansi_c(text: &str) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('a') => out.push('\x07'),
+            Some('b') => out.push('\x08'),
+            Some('e') | Some('E') => out.push('\x1b'),
+            Some('f') => out.push('\x0c'),
+            Some('v') => out.push('\x0b'),
+            Some(c @ ('\\' | '\'' | '"' | '?')) => out.push(c),
+            // \nnn (up to three octal digits) and \xHH (up to two hex digits)
+            Some(c @ '0'..='7') => {
+                let mut value = c.to_digit(8).unwrap();
+                for _ in 0..2 {
+                    match chars.peek().and_then(|d| d.to_digit(8)) {
+                        Some(d) => {
+                            value = value * 8 + d;
+                            chars.next();
+                        },
+                        None => break,
+                    }
+                }
+                out.extend(char::from_u32(value & 0xff));
+            },
+            Some('x') => {
+                let mut value = None;
+                for _ in 0..2 {
+                    match chars.peek().and_then(|d| d.to_digit(16)) {
+                        Some(d) => {
+                            value = Some(value.unwrap_or(0) * 16 + d);
+                            chars.next();
+                        },
+                        None => break,
+                    }
+                }
+                match value {
+                    Some(value) => out.extend(char::from_u32(value)),
+                    None => out.push_str("\\x"),
+                }
+            },
+            // \cX is control-X
+            Some('c') => match chars.next() {
+                Some(x) => out.extend(char::from_u32(x as u32 & 0x1f)),
+                None => out.push_str("\\c"),
+            },
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            },
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(word: Word, ifs: &str) -> Vec<String> {
+        word.split(ifs).iter().map(Word::text).collect()
+    }
+
+    #[test]
+    fn whitespace_runs_are_one_separator() {
+        assert_eq!(fields(Word::from_expansion("  a \t b\n", false), " \t\n"), ["a", "b"]);
+    }
+
+    #[test]
+    fn non_whitespace_ifs_makes_empty_fields() {
+        assert_eq!(fields(Word::from_expansion("a::b", false), ":"), ["a", "", "b"]);
+        // a leading separator ends an empty field, a trailing one does not
+        assert_eq!(fields(Word::from_expansion(":a:", false), ":"), ["", "a"]);
+        assert_eq!(fields(Word::from_expansion("::", false), ":"), ["", ""]);
+    }
+
+    #[test]
+    fn whitespace_around_other_separators_is_absorbed() {
+        assert_eq!(fields(Word::from_expansion("a : b", false), " :"), ["a", "b"]);
+        assert_eq!(fields(Word::from_expansion("a : : b", false), " :"), ["a", "", "b"]);
+    }
+
+    #[test]
+    fn quoted_text_is_not_split() {
+        let mut word = Word::default();
+        word.push_expansion("a b", true);
+        assert_eq!(fields(word, " "), ["a b"]);
+        let mut word = Word::default();
+        word.push("x", false);
+        word.push_expansion("a b", false);
+        assert_eq!(fields(word, " "), ["xa", "b"]);
+    }
+
+    #[test]
+    fn empty_words() {
+        // "" is an empty argument, an unquoted empty expansion is none
+        let mut word = Word::default();
+        word.push("", true);
+        assert_eq!(fields(word, " "), [""]);
+        assert!(fields(Word::from_expansion("", false), " ").is_empty());
+        assert!(fields(Word::from_expansion(" ", false), " ").is_empty());
+    }
+
+    #[test]
+    fn quoted_pattern_characters_are_escaped() {
+        let mut word = Word::default();
+        word.push("*", false);
+        word.push("*?", true);
+        assert_eq!(word.pattern(), "*\\*\\?");
+    }
+
+    #[test]
+    fn ansi_c_escapes() {
+        assert_eq!(ansi_c("a\\tb\\n"), "a\tb\n");
+        assert_eq!(ansi_c("\\x41\\101\\'"), "AA'");
+        assert_eq!(ansi_c("\\q"), "\\q");
+    }
+}